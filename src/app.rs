@@ -1,48 +1,118 @@
-use std::sync::Arc;
+use std::{fs, path::PathBuf, sync::Arc, time::Instant};
 
-use rodio::{OutputStream, Sink, source::SineWave};
+use rodio::{OutputStream, Sink};
 use winit::{
     application::ApplicationHandler,
+    dpi::PhysicalPosition,
     event::*,
     event_loop::ActiveEventLoop,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
     window::Window,
 };
 
+use chip8em::{Break, Chip8, Chip8Error, GdbControl, Symbols};
+
 use crate::{
-    chip8::{self, Chip8},
-    rect,
-    renderer::{QuadRenderer, Rect, Vertex},
-    time::Timer,
+    audio::{PatternHandle, PatternSource},
+    debugger::{self, Panel},
+    display::{AntiFlicker, Display},
+    effect::Effect,
+    gdb::GdbStub,
+    palette::Palette,
+    renderer::{QuadRenderer, Rect, Scaling, Screen},
+    rewind::Rewind,
+    scheduler::{self, Scheduler, Speed},
 };
 
+/// Frames of history kept for rewinding, 30 seconds at 60 FPS
+const REWIND_FRAMES: usize = 30 * 60;
+const MAX_CYCLES_PER_FRAME: u32 = 100_000;
+
+/// Settings the frontend starts with
+pub struct Config {
+    pub palette: Palette,
+    pub effect: Effect,
+    pub anti_flicker: AntiFlicker,
+    pub scaling: Scaling,
+    pub cycles_per_frame: u32,
+    pub speed: Speed,
+    pub paused: bool,
+    /// Labels shown in the debugger and breakpoint messages
+    pub symbols: Symbols,
+    /// Size of the loaded ROM, marked in the memory view
+    pub rom_size: usize,
+    /// Debugger connection controlling execution
+    pub gdb: Option<GdbStub>,
+}
+
 pub struct App {
     state: Option<QuadRenderer>,
     chip8: Chip8,
+    rom_path: PathBuf,
+    fault: Option<Chip8Error>,
+    /// Breakpoint that paused emulation, until it is resumed
+    last_break: Option<Break>,
+    symbols: Symbols,
+    gdb: Option<GdbStub>,
+    modifiers: ModifiersState,
+    rewind: Rewind,
+    rewinding: bool,
+    paused: bool,
+    /// The debugger panel when it is shown
     quads: Vec<Rect>,
-    color: [f32; 3],
-    timer: Timer,
-    clock_timer: Timer,
+    debugger: bool,
+    panel: Panel,
+    palette: Palette,
+    effect: Effect,
+    /// The picture of the framebuffer that is drawn
+    display: Display,
+    scaling: Scaling,
+    /// Where the framebuffer was last drawn, as given to the renderer
+    viewport: [f32; 4],
+    /// Framebuffer pixel under the mouse, shown in the debugger panel
+    hover: Option<(usize, usize)>,
+    scheduler: Scheduler,
     _stream: OutputStream,
     sink: Sink,
+    pattern: PatternHandle,
 }
 
 impl App {
-    pub fn new(color: [f32; 3], content: Vec<u8>) -> Self {
+    pub fn new(chip8: Chip8, rom_path: PathBuf, config: Config) -> Self {
         let (_stream, stream_handle) = OutputStream::try_default().expect("Unable to play audio");
         let sink = Sink::try_new(&stream_handle).expect("Unable to play audio");
         sink.pause();
-        sink.append(SineWave::new(440.0));
+        let (source, pattern) = PatternSource::new(chip8.audio_pattern(), chip8.playback_rate());
+        sink.append(source);
+        let panel = Panel::new(&chip8, config.rom_size);
+        let mut display = Display::new(config.anti_flicker);
+        display.show(&chip8);
 
         Self {
             state: None,
-            chip8: Chip8::new(content),
+            chip8,
+            rom_path,
+            fault: None,
+            last_break: None,
+            symbols: config.symbols,
+            gdb: config.gdb,
+            modifiers: ModifiersState::empty(),
+            rewind: Rewind::new(REWIND_FRAMES),
+            rewinding: false,
+            paused: config.paused,
             quads: vec![],
-            color,
-            timer: Timer::new(),
-            clock_timer: Timer::new(),
+            debugger: false,
+            panel,
+            palette: config.palette,
+            effect: config.effect,
+            display,
+            scaling: config.scaling,
+            viewport: [-1.0, 1.0, 1.0, -1.0],
+            hover: None,
+            scheduler: Scheduler::new(config.cycles_per_frame, config.speed),
             _stream,
             sink,
+            pattern,
         }
     }
 
@@ -71,33 +141,340 @@ impl App {
             return;
         };
 
-        self.chip8.update_keypad(code, pressed);
+        if pressed {
+            self.chip8.press_key(code);
+        } else {
+            self.chip8.release_key(code);
+        }
     }
 
-    pub fn update_quads(&mut self) {
-        self.quads.clear();
-        let chip8_width = chip8::CHIP8_WIDTH as f32;
-        let chip8_height = chip8::CHIP8_HEIGHT as f32;
-        let w = 2.0 / chip8_width;
-        let h = 2.0 / chip8_height;
-
-        for i in 0..chip8::CHIP8_WIDTH {
-            for j in 0..chip8::CHIP8_HEIGHT {
-                if self.chip8.screen[j][i] != 0 {
-                    let x = (i as f32) * 2.0 / chip8_width - 1.0;
-                    let y = 1.0 - (j as f32) * 2.0 / chip8_height;
-                    self.quads.push(rect!(x, y, w, h, self.color));
+    /// Handles emulator hotkeys, returning whether the key was consumed
+    fn handle_hotkey(&mut self, code: KeyCode, pressed: bool) -> bool {
+        let shift = self.modifiers.shift_key();
+        let cycles = self.scheduler.cycles_per_frame;
+
+        if self.debugger && self.panel.handle_key(code, pressed, &mut self.chip8, self.paused) {
+            // Memory may have been edited
+            self.update_panel();
+            return true;
+        }
+
+        match code {
+            KeyCode::Backspace => self.rewinding = pressed,
+            KeyCode::Space if pressed => {
+                self.paused = !self.paused;
+                if self.paused
+                    && let Some(gdb) = &mut self.gdb
+                {
+                    gdb.report_pause();
+                }
+                self.last_break = None;
+                self.update_title();
+            }
+            KeyCode::F12 if pressed => {
+                self.debugger = !self.debugger;
+                self.panel.reset_writes(&self.chip8);
+                self.update_screen();
+            }
+            KeyCode::F10 if pressed => {
+                self.palette = self.palette.next();
+                log::info!("Palette {}", self.palette.name().unwrap_or_default());
+                self.update_screen();
+            }
+            KeyCode::Period if pressed => self.advance_frame(),
+            KeyCode::Comma if pressed => self.advance_instruction(),
+            KeyCode::Tab if pressed => {
+                self.scheduler.speed = self.scheduler.speed.next();
+                self.update_title();
+            }
+            KeyCode::Equal if pressed => {
+                self.set_cycles_per_frame(if shift { cycles + 1 } else { cycles * 2 });
+            }
+            KeyCode::Minus if pressed => {
+                self.set_cycles_per_frame(if shift { cycles - 1 } else { cycles / 2 });
+            }
+            _ => {
+                let Some(slot) = state_slot(code) else {
+                    return false;
+                };
+                if !pressed {
+                    return true;
+                }
+                if shift {
+                    self.save_state(slot);
+                } else {
+                    self.load_state(slot);
+                }
+            }
+        }
+        true
+    }
+
+    fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.scheduler.cycles_per_frame = cycles.clamp(1, MAX_CYCLES_PER_FRAME);
+        self.update_title();
+    }
+
+    /// Runs a single frame while paused
+    fn advance_frame(&mut self) {
+        if !self.paused || self.fault.is_some() {
+            return;
+        }
+        match self.run_frame() {
+            Ok(Some(hit)) => self.report_break(hit),
+            Ok(None) => {}
+            Err(e) => self.report_fault(e),
+        }
+    }
+
+    /// Executes a single instruction while paused
+    fn advance_instruction(&mut self) {
+        if !self.paused || self.fault.is_some() {
+            return;
+        }
+        // A draw waiting for vblank holds the next instruction back until
+        // the frame ends
+        while self.chip8.waiting_for_vblank() {
+            self.end_frame();
+        }
+        let result = self.chip8.step();
+        if self.chip8.take_draw_flag() {
+            self.display.draw(&self.chip8);
+        }
+        if self.debugger {
+            self.panel.track_writes(&self.chip8);
+        }
+        match result {
+            Ok(Some(hit)) => self.report_break(hit),
+            Ok(None) => {
+                if let Some(gdb) = &mut self.gdb {
+                    gdb.report_step();
                 }
             }
+            Err(e) => self.report_fault(e),
+        }
+    }
+
+    /// Save state files live next to the ROM, e.g. `game.state1`
+    fn state_path(&self, slot: u8) -> PathBuf {
+        self.rom_path.with_extension(format!("state{slot}"))
+    }
+
+    fn save_state(&mut self, slot: u8) {
+        let path = self.state_path(slot);
+        match fs::write(&path, self.chip8.save_state()) {
+            Ok(()) => log::info!("Saved state to {}", path.display()),
+            Err(e) => log::error!("Unable to save state to {}: {e}", path.display()),
+        }
+    }
+
+    fn load_state(&mut self, slot: u8) {
+        let path = self.state_path(slot);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Unable to read state from {}: {e}", path.display());
+                return;
+            }
+        };
+
+        match self.chip8.load_state(&data) {
+            Ok(()) => {
+                log::info!("Loaded state from {}", path.display());
+                self.display.show(&self.chip8);
+                self.clear_fault();
+            }
+            Err(e) => log::error!("Unable to load state from {}: {e}", path.display()),
+        }
+    }
+
+    /// Runs one 60Hz frame worth of instructions, then ticks the timers.
+    ///
+    /// A breakpoint ends the frame early and is returned.
+    fn run_frame(&mut self) -> Result<Option<Break>, Chip8Error> {
+        let mut hit = None;
+        for _ in 0..self.scheduler.cycles_per_frame {
+            if self.chip8.exited() {
+                break;
+            }
+            hit = self.chip8.step()?;
+            if hit.is_some() {
+                break;
+            }
+        }
+
+        self.end_frame();
+        Ok(hit)
+    }
+
+    /// Ticks the timers and records the frame, as happens at vblank
+    fn end_frame(&mut self) {
+        self.chip8.tick_timers();
+        self.display.vblank(&self.chip8);
+        self.rewind.push(self.chip8.save_state());
+        if self.debugger {
+            self.panel.track_writes(&self.chip8);
+        }
+    }
+
+    /// Restores the previous frame from the rewind history
+    fn rewind_frame(&mut self) {
+        let Some(state) = self.rewind.pop() else {
+            return;
+        };
+        match self.chip8.load_state(state) {
+            Ok(()) => {
+                self.display.vblank(&self.chip8);
+                self.clear_fault();
+            }
+            Err(e) => log::error!("Unable to rewind: {e}"),
+        }
+    }
+
+    /// Pauses emulation and surfaces the fault in the window title
+    fn report_fault(&mut self, fault: Chip8Error) {
+        log::error!("Emulation halted: {fault}");
+        if let Some(gdb) = &mut self.gdb {
+            gdb.report_fault();
+        }
+        self.fault = Some(fault);
+        self.update_title();
+    }
+
+    /// Applies the requests of a connected gdb
+    fn poll_gdb(&mut self) {
+        let Some(gdb) = &mut self.gdb else {
+            return;
+        };
+
+        match gdb.poll(&mut self.chip8) {
+            Some(GdbControl::Continue | GdbControl::Detach | GdbControl::Step)
+                if self.fault.is_some() =>
+            {
+                gdb.report_fault();
+            }
+            Some(GdbControl::Continue | GdbControl::Detach) => {
+                self.paused = false;
+                self.last_break = None;
+                self.update_title();
+            }
+            Some(GdbControl::Pause) => {
+                self.paused = true;
+                self.update_title();
+            }
+            Some(GdbControl::Step) => {
+                self.paused = true;
+                self.advance_instruction();
+                self.update_title();
+            }
+            None => {}
+        }
+    }
+
+    /// Pauses emulation and shows the breakpoint in the window title
+    fn report_break(&mut self, hit: Break) {
+        log::info!("Stopped: {}", hit.describe(&self.symbols));
+        if let Some(gdb) = &mut self.gdb {
+            gdb.report_break(hit);
+        }
+        self.paused = true;
+        self.last_break = Some(hit);
+        self.update_title();
+    }
+
+    fn clear_fault(&mut self) {
+        if self.fault.take().is_some() {
+            self.update_title();
+        }
+    }
+
+    /// Shows the speed settings and emulation status in the window title
+    fn update_title(&self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+
+        let mut title = format!("chip8em - {} cycles/frame", self.scheduler.cycles_per_frame);
+        if self.scheduler.speed != Speed::Normal {
+            title += &format!(" - {}", self.scheduler.speed);
+        }
+        if self.paused {
+            title += " - paused";
+        }
+        if let Some(hit) = &self.last_break {
+            title += &format!(": {}", hit.describe(&self.symbols));
+        }
+        if let Some(fault) = &self.fault {
+            title += &format!(" - halted: {fault}");
+        }
+        state.window.set_title(&title);
+    }
+
+    /// Clip space x coordinate where the debugger panel starts
+    fn panel_left(&self) -> f32 {
+        match &self.state {
+            Some(state) if self.debugger => {
+                let size = state.window.inner_size();
+                1.0 - debugger::panel_width(size.width, size.height)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Uploads the picture of the framebuffer and where to draw it
+    pub fn update_screen(&mut self) {
+        let left = self.panel_left();
+        let Some(state) = &mut self.state else {
+            return;
+        };
+
+        // The ROM can switch resolution at any point
+        let size = self.display.size();
+        // The game is kept to the left of the debugger panel
+        self.viewport = self.scaling.viewport(state.window.inner_size(), left, size);
+        let colors = if state.is_srgb() { self.palette.linear() } else { self.palette.rgb() };
+        state.upload_framebuffer(self.display.pixels());
+        state.set_screen(&Screen {
+            colors: colors.map(|[r, g, b]| [r, g, b, 1.0]),
+            viewport: self.viewport,
+            size,
+            _padding: [0; 2],
+        });
+    }
+
+    /// Framebuffer pixel at a position in the window, if it is on the game
+    fn pixel_at(&self, position: PhysicalPosition<f64>) -> Option<(usize, usize)> {
+        let size = self.state.as_ref()?.window.inner_size();
+        let x = (position.x / size.width as f64 * 2.0 - 1.0) as f32;
+        let y = (1.0 - position.y / size.height as f64 * 2.0) as f32;
+
+        let [left, top, right, bottom] = self.viewport;
+        let [width, height] = self.display.size().map(|size| size as f32);
+        let column = (x - left) / (right - left) * width;
+        let row = (y - top) / (bottom - top) * height;
+        let on_game = (0.0..width).contains(&column) && (0.0..height).contains(&row);
+        on_game.then_some((column as usize, row as usize))
+    }
+
+    /// Redraws the debugger panel over the game
+    fn update_panel(&mut self) {
+        self.quads.clear();
+        if self.debugger {
+            let left = self.panel_left();
+            self.panel.draw(&mut self.quads, &self.chip8, &self.symbols, self.hover, left);
         }
     }
 }
 
 impl ApplicationHandler<QuadRenderer> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window_attributes = Window::default_attributes();
+        let window_attributes = Window::default_attributes().with_title("chip8em");
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
-        self.state = Some(pollster::block_on(QuadRenderer::new(window)));
+        let mut state = pollster::block_on(QuadRenderer::new(window));
+        state.set_effect(self.effect);
+        self.state = Some(state);
+        self.update_screen();
+        self.update_title();
     }
 
     fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: QuadRenderer) {
@@ -110,35 +487,70 @@ impl ApplicationHandler<QuadRenderer> for App {
         _window_id: winit::window::WindowId,
         event: WindowEvent,
     ) {
-        if let None = &self.state {
+        if self.state.is_none() {
             return;
         };
 
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::Resized(size) => {
-                self.update_quads();
                 self.state.as_mut().unwrap().resize(size.width, size.height);
+                self.update_screen();
             }
+            WindowEvent::CursorMoved { position, .. } => self.hover = self.pixel_at(position),
+            WindowEvent::CursorLeft { .. } => self.hover = None,
             WindowEvent::RedrawRequested => {
-                self.timer.update();
-                self.clock_timer.update();
-                self.chip8.tick(&mut self.timer, &mut self.clock_timer);
+                self.poll_gdb();
+                let frames = self.scheduler.frames_due();
+                if self.rewinding {
+                    // Rewinding always plays back at normal speed
+                    for _ in 0..frames {
+                        self.rewind_frame();
+                    }
+                } else if !self.paused && self.fault.is_none() {
+                    let start = Instant::now();
+                    for _ in 0..self.scheduler.speed.scale(frames) {
+                        if start.elapsed() >= scheduler::WORK_BUDGET || self.chip8.exited() {
+                            break;
+                        }
+                        match self.run_frame() {
+                            Ok(None) => {}
+                            Ok(Some(hit)) => {
+                                self.report_break(hit);
+                                break;
+                            }
+                            Err(e) => {
+                                self.report_fault(e);
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                if self.chip8.exited() {
+                    log::info!("ROM exited");
+                    event_loop.exit();
+                    return;
+                }
 
-                if self.chip8.beep {
+                self.pattern.set(self.chip8.audio_pattern(), self.chip8.playback_rate());
+                if self.chip8.sound_active() && !self.paused && self.fault.is_none() {
                     self.sink.play();
                 } else {
                     self.sink.pause();
                 }
 
-                if self.chip8.draw_flag {
-                    self.update_quads();
-                    self.chip8.draw_flag = false;
+                if self.chip8.take_draw_flag() {
+                    self.display.draw(&self.chip8);
+                }
+                if self.display.take_dirty() {
+                    self.update_screen();
                 }
+                self.update_panel();
 
                 let state = self.state.as_mut().unwrap();
 
-                match state.render_quads(self.quads.as_slice()) {
+                match state.render(self.quads.as_slice()) {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         let size = state.window.inner_size();
@@ -158,12 +570,33 @@ impl ApplicationHandler<QuadRenderer> for App {
                         ..
                     },
                 ..
-            } => {
-                if !repeat {
-                    self.update_keypad(code, key_state.is_pressed());
+            } if !repeat => {
+                if self.handle_hotkey(code, key_state.is_pressed()) {
+                    return;
                 }
+                self.update_keypad(code, key_state.is_pressed());
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
             }
             _ => {}
         }
     }
 }
+
+/// Save state slot bound to a function key
+fn state_slot(code: KeyCode) -> Option<u8> {
+    let slot = match code {
+        KeyCode::F1 => 1,
+        KeyCode::F2 => 2,
+        KeyCode::F3 => 3,
+        KeyCode::F4 => 4,
+        KeyCode::F5 => 5,
+        KeyCode::F6 => 6,
+        KeyCode::F7 => 7,
+        KeyCode::F8 => 8,
+        KeyCode::F9 => 9,
+        _ => return None,
+    };
+    Some(slot)
+}
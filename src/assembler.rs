@@ -0,0 +1,925 @@
+//! Assembler for the Octo language.
+//!
+//! Supports labels, `:const`, `:alias`, `:macro`, `:calc`, `:byte`, `:org`,
+//! structured `if`/`then`/`begin`/`else`/`end` and `loop`/`while`/`again`,
+//! and the CHIP-8, SUPER-CHIP and XO-CHIP instructions.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{error::AsmError, symbols::Symbols};
+
+const START: usize = 0x200;
+/// Macro expansions allowed before giving up on a recursive macro
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Output of [`assemble`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// Binary to be loaded at 0x200
+    pub rom: Vec<u8>,
+    /// Address of every label
+    pub symbols: Symbols,
+}
+
+/// Assembles Octo source into a ROM
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut asm = Assembler::new(source);
+
+    // Execution starts at 0x200, so jump to main unless it is defined there
+    let first: Vec<_> = asm.tokens.iter().take(2).map(|token| token.text.as_str()).collect();
+    let has_main = asm.tokens.iter().zip(asm.tokens.iter().skip(1)).any(|(colon, name)| {
+        colon.text == ":" && name.text == "main"
+    });
+    if has_main && first != [":", "main"] {
+        asm.fixup(Fixup::Nnn, "main".to_string(), 1);
+        asm.emit_op(0x1000);
+    }
+
+    while !asm.tokens.is_empty() {
+        asm.statement()?;
+    }
+    if let Some(block) = asm.blocks.last() {
+        let what = match block {
+            Block::If { .. } => "if ... begin without end",
+            Block::Loop { .. } => "loop without again",
+        };
+        return Err(AsmError { line: asm.line, message: what.to_string() });
+    }
+
+    asm.resolve()?;
+    let mut symbols = Symbols::new();
+    for (name, addr) in asm.labels {
+        symbols.insert(name, addr);
+    }
+    Ok(Program { rom: asm.rom, symbols })
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// Operand patched in once all labels are known
+#[derive(Clone, Copy)]
+enum Fixup {
+    /// Low 12 bits of an instruction
+    Nnn,
+    /// 16-bit address following `F000`
+    Long,
+}
+
+/// Open `if ... begin` or `loop`
+enum Block {
+    If { jump: usize },
+    Loop { start: usize, breaks: Vec<usize> },
+}
+
+/// Condition of `if` and `while`
+#[derive(Clone, Copy)]
+enum Cond {
+    EqImm(u8, u8),
+    NeImm(u8, u8),
+    EqReg(u8, u8),
+    NeReg(u8, u8),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Cond {
+    fn negate(self) -> Self {
+        match self {
+            Self::EqImm(x, kk) => Self::NeImm(x, kk),
+            Self::NeImm(x, kk) => Self::EqImm(x, kk),
+            Self::EqReg(x, y) => Self::NeReg(x, y),
+            Self::NeReg(x, y) => Self::EqReg(x, y),
+            Self::Key(x) => Self::NotKey(x),
+            Self::NotKey(x) => Self::Key(x),
+        }
+    }
+
+    /// Instruction skipping the next one when the condition holds
+    fn skip_op(self) -> u16 {
+        let x = |x: u8| (x as u16) << 8;
+        let y = |y: u8| (y as u16) << 4;
+        match self {
+            Self::EqImm(vx, kk) => 0x3000 | x(vx) | kk as u16,
+            Self::NeImm(vx, kk) => 0x4000 | x(vx) | kk as u16,
+            Self::EqReg(vx, vy) => 0x5000 | x(vx) | y(vy),
+            Self::NeReg(vx, vy) => 0x9000 | x(vx) | y(vy),
+            Self::Key(vx) => 0xe09e | x(vx),
+            Self::NotKey(vx) => 0xe0a1 | x(vx),
+        }
+    }
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    /// Line of the last token taken
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: BTreeMap<String, usize>,
+    consts: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(usize, Fixup, String, usize)>,
+    blocks: Vec<Block>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Self {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                let code = line.split('#').next().unwrap_or_default();
+                code.split_whitespace()
+                    .map(move |text| Token { text: text.to_string(), line: i + 1 })
+            })
+            .collect();
+
+        Self {
+            tokens,
+            line: 1,
+            rom: vec![],
+            here: START,
+            labels: BTreeMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: vec![],
+            blocks: vec![],
+            expansions: 0,
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(AsmError { line: self.line, message: message.into() })
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of source"),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected {expected}, found {token}"));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                if self.labels.insert(name.clone(), self.here).is_some() {
+                    return self.error(format!("label {name} is defined twice"));
+                }
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.consts.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.braced_expression()?;
+                self.consts.insert(name, value);
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.braced_expression()?
+                } else {
+                    self.value()?
+                };
+                let byte = self.to_byte(value)?;
+                self.emit(byte);
+            }
+            ":org" => {
+                let addr = self.value()?;
+                if !(START as i64..=0xffff).contains(&addr) {
+                    return self.error(format!("cannot place code at {addr:#x}"));
+                }
+                self.here = addr as usize;
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => self.address_op(0x2000)?,
+            "jump" => self.address_op(0x1000)?,
+            "jump0" => self.address_op(0xb000)?,
+            "return" | ";" => self.emit_op(0x00ee),
+            "clear" => self.emit_op(0x00e0),
+            "exit" => self.emit_op(0x00fd),
+            "lores" => self.emit_op(0x00fe),
+            "hires" => self.emit_op(0x00ff),
+            "scroll-right" => self.emit_op(0x00fb),
+            "scroll-left" => self.emit_op(0x00fc),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit_op(0x00c0 | n);
+            }
+            "audio" => self.emit_op(0xf002),
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit_op(0xf001 | n << 8);
+            }
+            "bcd" => self.reg_op(0xf033)?,
+            "saveflags" => self.reg_op(0xf075)?,
+            "loadflags" => self.reg_op(0xf085)?,
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let op = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.emit_op(op | (x as u16) << 8 | (y as u16) << 4);
+                } else {
+                    let op = if token == "save" { 0xf055 } else { 0xf065 };
+                    self.emit_op(op | (x as u16) << 8);
+                }
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.nibble()?;
+                self.emit_op(0xd000 | x << 8 | y << 4 | n);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let op = match token.as_str() {
+                    "delay" => 0xf015,
+                    "buzzer" => 0xf018,
+                    _ => 0xf03a,
+                };
+                self.reg_op(op)?;
+            }
+            "i" => self.index_statement()?,
+            "if" => self.if_statement()?,
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let end = self.here;
+                    self.emit_op(0x1000);
+                    self.patch_jump(jump, self.here)?;
+                    self.blocks.push(Block::If { jump: end });
+                }
+                _ => return self.error("else without if ... begin"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump }) => self.patch_jump(jump, self.here)?,
+                _ => return self.error("end without if ... begin"),
+            },
+            "loop" => self.blocks.push(Block::Loop { start: self.here, breaks: vec![] }),
+            "while" => {
+                let cond = self.condition()?;
+                let exit = self.here + 2;
+                self.emit_op(cond.skip_op());
+                self.emit_op(0x1000);
+                let mut blocks = self.blocks.iter_mut().rev();
+                match blocks.find(|block| matches!(block, Block::Loop { .. })) {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(exit),
+                    _ => return self.error("while outside of a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks }) => {
+                    let op = self.jump_op(start)?;
+                    self.emit_op(op);
+                    for jump in breaks {
+                        self.patch_jump(jump, self.here)?;
+                    }
+                }
+                _ => return self.error("again without loop"),
+            },
+            _ => {
+                if let Some(x) = self.parse_register(&token) {
+                    return self.register_statement(x);
+                }
+                if self.labels.contains_key(&token) {
+                    let addr = self.labels[&token];
+                    return self.emit_call(addr);
+                }
+                if let Some(value) = self.parse_value(&token) {
+                    let byte = self.to_byte(value)?;
+                    self.emit(byte);
+                    return Ok(());
+                }
+                if self.macros.contains_key(&token) {
+                    return self.expand_macro(&token);
+                }
+                if token.starts_with(':') || !is_name(&token) {
+                    return self.error(format!("unexpected {token}"));
+                }
+                // A bare name calls the subroutine with that label
+                self.fixup(Fixup::Nnn, token, self.line);
+                self.emit_op(0x2000);
+            }
+        }
+        Ok(())
+    }
+
+    /// `vX := ...`, `vX += ...` and the other register operations
+    fn register_statement(&mut self, x: u8) -> Result<(), AsmError> {
+        let x16 = (x as u16) << 8;
+        let op = self.next()?;
+        let rhs = self.next()?;
+
+        let reg_op = |n: u16| self.parse_register(&rhs).map(|y| 0x8000 | x16 | (y as u16) << 4 | n);
+        let opcode = match op.as_str() {
+            ":=" => match rhs.as_str() {
+                "random" => {
+                    let kk = self.byte()?;
+                    0xc000 | x16 | kk as u16
+                }
+                "delay" => 0xf007 | x16,
+                "key" => 0xf00a | x16,
+                _ => match reg_op(0x0) {
+                    Some(op) => op,
+                    None => 0x6000 | x16 | self.byte_of(&rhs)? as u16,
+                },
+            },
+            "+=" => match reg_op(0x4) {
+                Some(op) => op,
+                None => 0x7000 | x16 | self.byte_of(&rhs)? as u16,
+            },
+            // Unlike 8XY5, 7XNN leaves VF alone, so there is no immediate form
+            "-=" => reg_op(0x5).map_or_else(|| self.error("-= needs a register"), Ok)?,
+            "|=" => reg_op(0x1).map_or_else(|| self.error("|= needs a register"), Ok)?,
+            "&=" => reg_op(0x2).map_or_else(|| self.error("&= needs a register"), Ok)?,
+            "^=" => reg_op(0x3).map_or_else(|| self.error("^= needs a register"), Ok)?,
+            ">>=" => reg_op(0x6).map_or_else(|| self.error(">>= needs a register"), Ok)?,
+            "=-" => reg_op(0x7).map_or_else(|| self.error("=- needs a register"), Ok)?,
+            "<<=" => reg_op(0xe).map_or_else(|| self.error("<<= needs a register"), Ok)?,
+            _ => return self.error(format!("unknown operator {op}")),
+        };
+        self.emit_op(opcode);
+        Ok(())
+    }
+
+    /// `i := ...` and `i += vX`
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        if op == "+=" {
+            return self.reg_op(0xf01e);
+        }
+        if op != ":=" {
+            return self.error(format!("unknown operator {op}"));
+        }
+
+        match self.peek() {
+            Some("hex") => {
+                self.next()?;
+                self.reg_op(0xf029)
+            }
+            Some("bighex") => {
+                self.next()?;
+                self.reg_op(0xf030)
+            }
+            Some("long") => {
+                self.next()?;
+                self.emit_op(0xf000);
+                let token = self.next()?;
+                match self.parse_value(&token) {
+                    Some(addr @ 0..=0xffff) => self.emit_op(addr as u16),
+                    Some(addr) => return self.error(format!("address {addr:#x} out of range")),
+                    None => {
+                        self.fixup(Fixup::Long, token, self.line);
+                        self.emit_op(0);
+                    }
+                }
+                Ok(())
+            }
+            _ => self.address_op(0xa000),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let cond = self.condition()?;
+        match self.next()?.as_str() {
+            "then" => {
+                // Skip the next statement unless the condition holds
+                self.emit_op(cond.negate().skip_op());
+                self.statement()
+            }
+            "begin" => {
+                self.emit_op(cond.skip_op());
+                self.blocks.push(Block::If { jump: self.here });
+                self.emit_op(0x1000);
+                Ok(())
+            }
+            token => self.error(format!("expected then or begin, found {token}")),
+        }
+    }
+
+    /// Parses `vX <op> <rhs>`, emitting the comparison into VF first for the
+    /// ordering operators
+    fn condition(&mut self) -> Result<Cond, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+        match op.as_str() {
+            "key" => return Ok(Cond::Key(x)),
+            "-key" => return Ok(Cond::NotKey(x)),
+            _ => {}
+        }
+
+        let rhs = self.next()?;
+        let y = self.parse_register(&rhs);
+        let kk = match y {
+            Some(_) => 0,
+            None => self.byte_of(&rhs)?,
+        };
+        let (x16, vf) = ((x as u16) << 8, 0xf00);
+
+        match op.as_str() {
+            "==" => return Ok(y.map_or(Cond::EqImm(x, kk), |y| Cond::EqReg(x, y))),
+            "!=" => return Ok(y.map_or(Cond::NeImm(x, kk), |y| Cond::NeReg(x, y))),
+            "<" | ">=" => {
+                // VF = VX >= rhs
+                match y {
+                    Some(y) => {
+                        self.emit_op(0x8000 | vf | x16 >> 4);
+                        self.emit_op(0x8005 | vf | (y as u16) << 4);
+                    }
+                    None => {
+                        self.emit_op(0x6000 | vf | kk as u16);
+                        self.emit_op(0x8007 | vf | x16 >> 4);
+                    }
+                }
+            }
+            ">" | "<=" => {
+                // VF = rhs >= VX
+                match y {
+                    Some(y) => {
+                        self.emit_op(0x8000 | vf | (y as u16) << 4);
+                        self.emit_op(0x8005 | vf | x16 >> 4);
+                    }
+                    None => {
+                        self.emit_op(0x6000 | vf | kk as u16);
+                        self.emit_op(0x8005 | vf | x16 >> 4);
+                    }
+                }
+            }
+            _ => return self.error(format!("unknown comparison {op}")),
+        }
+
+        Ok(match op.as_str() {
+            "<" | ">" => Cond::EqImm(0xf, 0),
+            _ => Cond::NeImm(0xf, 0),
+        })
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut args = vec![];
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            args.push(token);
+        }
+
+        let mut body = vec![];
+        let mut depth = 1;
+        loop {
+            let Some(token) = self.tokens.pop_front() else {
+                return self.error(format!("macro {name} is not closed"));
+            };
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { args, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return self.error(format!("too many macro expansions, is {name} recursive?"));
+        }
+
+        let count = self.macros[name].args.len();
+        let mut values = HashMap::new();
+        for i in 0..count {
+            let value = self.next()?;
+            values.insert(self.macros[name].args[i].clone(), value);
+        }
+
+        let line = self.line;
+        let body = self.macros[name].body.iter().rev().map(|token| Token {
+            text: values.get(&token.text).unwrap_or(&token.text).clone(),
+            line,
+        });
+        for token in body {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// Evaluates `{ expr }`. Like Octo, operators have no precedence and
+    /// are applied from right to left.
+    fn braced_expression(&mut self) -> Result<i64, AsmError> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<i64, AsmError> {
+        let lhs = self.term()?;
+        let op = match self.peek() {
+            Some(op) if is_binary_operator(op) => op.to_string(),
+            _ => return Ok(lhs),
+        };
+        self.next()?;
+        let rhs = self.expression()?;
+
+        let value = match op.as_str() {
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" | "%" if rhs == 0 => return self.error("division by zero"),
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => lhs & rhs,
+            "|" => lhs | rhs,
+            "^" => lhs ^ rhs,
+            "<<" => lhs.wrapping_shl(rhs as u32),
+            ">>" => lhs.wrapping_shr(rhs as u32),
+            "<" => (lhs < rhs) as i64,
+            ">" => (lhs > rhs) as i64,
+            "<=" => (lhs <= rhs) as i64,
+            ">=" => (lhs >= rhs) as i64,
+            "==" => (lhs == rhs) as i64,
+            "!=" => (lhs != rhs) as i64,
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            _ => unreachable!("{op} is not a binary operator"),
+        };
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "-" => Ok(self.term()?.wrapping_neg()),
+            "~" => Ok(!self.term()?),
+            "!" => Ok((self.term()? == 0) as i64),
+            "HERE" => Ok(self.here as i64),
+            _ => match self.parse_value(&token) {
+                Some(value) => Ok(value),
+                None => self.error(format!("unknown value {token}")),
+            },
+        }
+    }
+
+    fn name(&mut self) -> Result<String, AsmError> {
+        let name = self.next()?;
+        if !is_name(&name) {
+            return self.error(format!("invalid name {name}"));
+        }
+        Ok(name)
+    }
+
+    fn parse_register(&self, token: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+        let digit = token.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        match self.parse_register(&token) {
+            Some(reg) => Ok(reg),
+            None => self.error(format!("expected a register, found {token}")),
+        }
+    }
+
+    /// A number, constant or already defined label
+    fn parse_value(&self, token: &str) -> Option<i64> {
+        if let Some(&value) = self.consts.get(token) {
+            return Some(value);
+        }
+        if let Some(&addr) = self.labels.get(token) {
+            return Some(addr as i64);
+        }
+
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()?
+        } else {
+            digits.parse().ok()?
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn value(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        match self.parse_value(&token) {
+            Some(value) => Ok(value),
+            None => self.error(format!("unknown value {token}")),
+        }
+    }
+
+    fn to_byte(&self, value: i64) -> Result<u8, AsmError> {
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("{value} does not fit in a byte"));
+        }
+        Ok(value as u8)
+    }
+
+    fn byte_of(&self, token: &str) -> Result<u8, AsmError> {
+        match self.parse_value(token) {
+            Some(value) => self.to_byte(value),
+            None => self.error(format!("unknown value {token}")),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.byte_of(&token)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AsmError> {
+        match self.value()? {
+            n @ 0..=15 => Ok(n as u16),
+            n => self.error(format!("{n} does not fit in a nibble")),
+        }
+    }
+
+    /// Emits `op | X` for an `FX..` style instruction taking a register
+    fn reg_op(&mut self, op: u16) -> Result<(), AsmError> {
+        let x = self.register()?;
+        self.emit_op(op | (x as u16) << 8);
+        Ok(())
+    }
+
+    /// Emits `op | NNN`, resolving the address later if it is a label
+    fn address_op(&mut self, op: u16) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match self.parse_value(&token) {
+            Some(addr @ 0..=0xfff) => self.emit_op(op | addr as u16),
+            Some(addr) => return self.error(format!("address {addr:#x} out of range")),
+            None if is_name(&token) => {
+                self.fixup(Fixup::Nnn, token, self.line);
+                self.emit_op(op);
+            }
+            None => return self.error(format!("expected an address, found {token}")),
+        }
+        Ok(())
+    }
+
+    fn emit_call(&mut self, addr: usize) -> Result<(), AsmError> {
+        if addr > 0xfff {
+            return self.error(format!("address {addr:#x} out of range"));
+        }
+        self.emit_op(0x2000 | addr as u16);
+        Ok(())
+    }
+
+    fn fixup(&mut self, kind: Fixup, name: String, line: usize) {
+        self.fixups.push((self.here, kind, name, line));
+    }
+
+    fn emit(&mut self, byte: u8) {
+        let offset = self.here - START;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+    }
+
+    fn emit_op(&mut self, op: u16) {
+        self.emit((op >> 8) as u8);
+        self.emit(op as u8);
+    }
+
+    /// `1NNN` jumping to `target`, if it is within reach
+    fn jump_op(&self, target: usize) -> Result<u16, AsmError> {
+        if target > 0xfff {
+            return self.error(format!("target {target:#x} out of range for jump"));
+        }
+        Ok(0x1000 | target as u16)
+    }
+
+    /// Points the jump emitted at `at` to `target`
+    fn patch_jump(&mut self, at: usize, target: usize) -> Result<(), AsmError> {
+        let [high, low] = self.jump_op(target)?.to_be_bytes();
+        let offset = at - START;
+        self.rom[offset] = high;
+        self.rom[offset + 1] = low;
+        Ok(())
+    }
+
+    fn resolve(&mut self) -> Result<(), AsmError> {
+        for (at, kind, name, line) in std::mem::take(&mut self.fixups) {
+            let Some(&addr) = self.labels.get(&name) else {
+                return Err(AsmError { line, message: format!("undefined label {name}") });
+            };
+
+            let offset = at - START;
+            match kind {
+                Fixup::Nnn => {
+                    if addr > 0xfff {
+                        let message =
+                            format!("label {name} at {addr:#x} is out of reach, use i := long");
+                        return Err(AsmError { line, message });
+                    }
+                    self.rom[offset] |= (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+                Fixup::Long => {
+                    self.rom[offset] = (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    token.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_binary_operator(token: &str) -> bool {
+    matches!(
+        token,
+        "+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "<" | ">" | "<=" | ">="
+            | "==" | "!=" | "min" | "max"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        match assemble(source) {
+            Ok(program) => program.rom,
+            Err(e) => panic!("{source}: {e}"),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        match assemble(source) {
+            Ok(program) => panic!("{source} assembled to {:02x?}", program.rom),
+            Err(e) => e.message,
+        }
+    }
+
+    #[test]
+    fn instructions() {
+        let cases: &[(&str, &[u8])] = &[
+            (": main clear return", &[0x00, 0xe0, 0x00, 0xee]),
+            (": main v3 := 0x2a v3 += v4", &[0x63, 0x2a, 0x83, 0x44]),
+            (": main v1 -= v2 v1 =- v2", &[0x81, 0x25, 0x81, 0x27]),
+            (": main sprite v0 v1 5", &[0xd0, 0x15]),
+            (": main save v2 - v5", &[0x52, 0x52]),
+            (": main :alias x v7 x += 1", &[0x77, 0x01]),
+            (": main :byte 0xff :byte { 1 + 2 }", &[0xff, 0x03]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(rom(source), *expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn jumps_to_main() {
+        assert_eq!(rom(": sub return : main sub"), [0x12, 0x04, 0x00, 0xee, 0x22, 0x02]);
+    }
+
+    #[test]
+    fn loops() {
+        let source = ": main loop v0 += 1 while v0 != 5 again";
+        assert_eq!(rom(source), [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn if_then() {
+        assert_eq!(rom(": main if v0 == 1 then v1 := 2"), [0x40, 0x01, 0x61, 0x02]);
+        assert_eq!(rom(": main if v0 key then v1 := 2"), [0xe0, 0xa1, 0x61, 0x02]);
+    }
+
+    #[test]
+    fn if_else() {
+        let source = ": main if v0 == 1 begin v1 := 2 else v1 := 3 end";
+        let expected = [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0a, 0x61, 0x03];
+        assert_eq!(rom(source), expected);
+    }
+
+    #[test]
+    fn comparisons() {
+        let cases: &[(&str, &[u8])] = &[
+            ("<", &[0x6f, 0x05, 0x8f, 0x07, 0x4f, 0x00]),
+            (">=", &[0x6f, 0x05, 0x8f, 0x07, 0x3f, 0x00]),
+            (">", &[0x6f, 0x05, 0x8f, 0x05, 0x4f, 0x00]),
+            ("<=", &[0x6f, 0x05, 0x8f, 0x05, 0x3f, 0x00]),
+        ];
+        for (op, expected) in cases {
+            let mut expected = expected.to_vec();
+            expected.extend([0x61, 0x01]);
+            assert_eq!(rom(&format!(": main if v0 {op} 5 then v1 := 1")), expected, "{op}");
+        }
+
+        let expected = [0x8f, 0x00, 0x8f, 0x15, 0x4f, 0x00, 0x61, 0x01];
+        assert_eq!(rom(": main if v0 < v1 then v1 := 1"), expected);
+    }
+
+    #[test]
+    fn macros() {
+        let source = ":macro add-to reg n { reg += n } : main add-to v2 7 add-to v3 1";
+        assert_eq!(rom(source), [0x12, 0x02, 0x72, 0x07, 0x73, 0x01]);
+    }
+
+    #[test]
+    fn calc() {
+        // Operators are applied from right to left: 10 - (2 - 3)
+        assert_eq!(rom(":calc x { 10 - 2 - 3 } : main v0 := x"), [0x12, 0x02, 0x60, 0x0b]);
+        let source = ":const a 4 :calc b { a * 2 + 1 } : main v0 := b";
+        assert_eq!(rom(source), [0x12, 0x02, 0x60, 0x0c]);
+    }
+
+    #[test]
+    fn forward_references() {
+        assert_eq!(rom(": main jump later : later clear"), [0x12, 0x02, 0x00, 0xe0]);
+        assert_eq!(rom(": main i := data : data 0x3c"), [0xa2, 0x02, 0x3c]);
+        assert_eq!(rom(": main sub : sub return"), [0x22, 0x02, 0x00, 0xee]);
+    }
+
+    #[test]
+    fn long_index() {
+        let program = assemble(": main i := long data :org 0x1200 : data 0xab").unwrap();
+        assert_eq!(program.rom[..4], [0xf0, 0x00, 0x12, 0x00]);
+        assert_eq!(program.rom[0x1000], 0xab);
+        assert_eq!(program.symbols.addr("data"), Some(0x1200));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            (": main i := data :org 0x1200 : data 0", "out of reach"),
+            (":org 0x1200 : main loop v0 += 1 again", "target 0x1200 out of range for jump"),
+            (":org 0xffa : main loop v0 += 1 while v0 != 3 again", "target 0x1002"),
+            (":org 0x1200 : main if v0 == 1 begin clear end", "target 0x1206"),
+            (": main if v0 == 1 begin clear :org 0x1200 else clear end", "target 0x1202"),
+            (": main v0 -= 5", "-= needs a register"),
+            (": main v0 := 256", "does not fit in a byte"),
+            (": main jump nowhere", "undefined label nowhere"),
+            (": main loop clear", "loop without again"),
+            (": main end", "end without if"),
+            (": a clear : a clear", "defined twice"),
+        ];
+        for (source, expected) in cases {
+            let message = error(source);
+            assert!(message.contains(expected), "{source}: {message}");
+        }
+    }
+}
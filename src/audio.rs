@@ -0,0 +1,99 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+
+const SAMPLE_RATE: u32 = 48000;
+const PATTERN_BITS: f32 = 128.0;
+
+/// Pattern buffer and playback rate of the audio
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pattern {
+    bits: [u8; 16],
+    rate: f32,
+}
+
+/// Pattern shared between the emulator and the audio thread
+struct Shared {
+    pattern: Mutex<Pattern>,
+    /// Bumped on every change, so the audio thread only locks the pattern
+    /// when there is something new to copy
+    generation: AtomicU64,
+}
+
+/// Handle used by the frontend to update what the audio thread plays
+#[derive(Clone)]
+pub struct PatternHandle(Arc<Shared>);
+
+impl PatternHandle {
+    pub fn set(&self, bits: &[u8; 16], rate: f32) {
+        let mut pattern = self.0.pattern.lock().unwrap();
+        let new = Pattern { bits: *bits, rate };
+        if *pattern != new {
+            *pattern = new;
+            self.0.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+}
+
+/// Infinite source looping over the XO-CHIP 1-bit audio pattern buffer
+pub struct PatternSource {
+    shared: Arc<Shared>,
+    /// Copy of the shared pattern as of `generation`
+    pattern: Pattern,
+    generation: u64,
+    position: f32,
+}
+
+impl PatternSource {
+    pub fn new(bits: &[u8; 16], rate: f32) -> (Self, PatternHandle) {
+        let pattern = Pattern { bits: *bits, rate };
+        let shared =
+            Arc::new(Shared { pattern: Mutex::new(pattern), generation: AtomicU64::new(0) });
+        let source = Self { shared: shared.clone(), pattern, generation: 0, position: 0.0 };
+        (source, PatternHandle(shared))
+    }
+}
+
+impl Iterator for PatternSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let generation = self.shared.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.pattern = *self.shared.pattern.lock().unwrap();
+            self.generation = generation;
+        }
+        let Pattern { bits, rate } = self.pattern;
+
+        let bit = self.position as usize;
+        let sample = (bits[bit / 8] >> (7 - bit % 8)) & 1;
+
+        self.position = (self.position + rate / SAMPLE_RATE as f32) % PATTERN_BITS;
+
+        Some(if sample == 1 { 0.25 } else { -0.25 })
+    }
+}
+
+impl Source for PatternSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
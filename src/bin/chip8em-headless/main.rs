@@ -0,0 +1,246 @@
+//! Runs a ROM without a window or audio device and dumps the final machine
+//! state, for automated testing on CI machines.
+
+use std::{env, fs, process::ExitCode};
+
+use chip8em::{Chip8, Chip8Error, DEFAULT_CYCLES_PER_FRAME, Quirks, Rng};
+
+mod png;
+
+const USAGE: &str = "\
+Usage: chip8em-headless [options] <rom>
+
+Options:
+  --quirks <profile>        Quirk profile: vip, chip48, schip, xochip or octo
+  --frames <n>              Number of 60Hz frames to run
+  --cycles <n>              Number of instructions to run
+  --cycles-per-frame <n>    Instructions executed per frame (default 8)
+  --seed <n>                Seed for the CXNN random number generator (default 0)
+  --rng-script <hex>        Bytes CXNN returns in order instead, e.g. 00ff80
+  --key <frame>:<key>[:<n>] Hold hex key <key> for <n> frames (default 1)
+                            starting at <frame>, may be repeated
+  --screen <path>           Write the framebuffer as PNG (.png) or text, - for stdout
+  --regs <path>             Write the registers as JSON, - for stdout";
+
+const PNG_PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+struct KeyPress {
+    frame: u64,
+    key: u8,
+    frames: u64,
+}
+
+struct Options {
+    rom: String,
+    quirks: Quirks,
+    rng: Rng,
+    frames: Option<u64>,
+    cycles: Option<u64>,
+    cycles_per_frame: u64,
+    keys: Vec<KeyPress>,
+    screen: Option<String>,
+    regs: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let content = match fs::read(&options.rom) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Unable to read {}: {e}", options.rom);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut chip8 = match Chip8::new(content, options.quirks) {
+        Ok(chip8) => chip8,
+        Err(e) => {
+            eprintln!("Unable to load ROM: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    chip8.set_rng(options.rng.clone());
+    let fault = run(&mut chip8, &options).err();
+    if let Some(fault) = &fault {
+        eprintln!("Emulation halted: {fault}");
+    }
+
+    if let Some(path) = &options.screen {
+        let data = if path.ends_with(".png") {
+            screen_png(&chip8)
+        } else {
+            chip8.screen_text().into_bytes()
+        };
+        if let Err(e) = output(path, &data) {
+            eprintln!("Unable to write {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(path) = &options.regs
+        && let Err(e) = output(path, registers_json(&chip8, fault.as_ref()).as_bytes())
+    {
+        eprintln!("Unable to write {path}: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    if fault.is_some() { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+fn run(chip8: &mut Chip8, options: &Options) -> Result<(), Chip8Error> {
+    let mut executed = 0;
+    let mut frame = 0;
+
+    loop {
+        if options.frames.is_some_and(|frames| frame >= frames) {
+            return Ok(());
+        }
+
+        for press in &options.keys {
+            if press.frame == frame {
+                chip8.press_key(press.key);
+            } else if press.frame + press.frames == frame {
+                chip8.release_key(press.key);
+            }
+        }
+
+        for _ in 0..options.cycles_per_frame {
+            if options.cycles.is_some_and(|cycles| executed >= cycles) || chip8.exited() {
+                return Ok(());
+            }
+            // Nothing runs until the next tick, so the frame is over early
+            if chip8.waiting_for_vblank() {
+                break;
+            }
+            chip8.step()?;
+            executed += 1;
+        }
+
+        chip8.tick_timers();
+        frame += 1;
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut rom = None;
+    let mut options = Options {
+        rom: String::new(),
+        quirks: Quirks::default(),
+        rng: Rng::seeded(0),
+        frames: None,
+        cycles: None,
+        cycles_per_frame: DEFAULT_CYCLES_PER_FRAME as u64,
+        keys: vec![],
+        screen: None,
+        regs: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--quirks" => {
+                let name = value()?;
+                options.quirks =
+                    Quirks::from_name(&name).ok_or_else(|| format!("Unknown quirk profile {name}"))?;
+            }
+            "--seed" => options.rng = Rng::seeded(parse_number(&value()?)?),
+            "--rng-script" => options.rng = Rng::scripted(parse_hex(&value()?)?),
+            "--frames" => options.frames = Some(parse_number(&value()?)?),
+            "--cycles" => options.cycles = Some(parse_number(&value()?)?),
+            "--cycles-per-frame" => {
+                let cycles = value()?;
+                options.cycles_per_frame = parse_number(&cycles)?;
+                if options.cycles_per_frame == 0 {
+                    return Err(format!("Invalid cycles per frame {cycles}"));
+                }
+            }
+            "--key" => options.keys.push(parse_key(&value()?)?),
+            "--screen" => options.screen = Some(value()?),
+            "--regs" => options.regs = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => rom = Some(arg),
+        }
+    }
+
+    options.rom = rom.ok_or("Need a ROM path")?;
+    if options.frames.is_none() && options.cycles.is_none() {
+        return Err("Need --frames or --cycles".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    s.parse().map_err(|_| format!("Invalid number {s}"))
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err(format!("Odd number of hex digits in {s}"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| format!("Invalid hex {s}")))
+        .collect()
+}
+
+fn parse_key(s: &str) -> Result<KeyPress, String> {
+    let mut parts = s.split(':');
+    let frame = parse_number(parts.next().unwrap_or_default())?;
+    let key = parts
+        .next()
+        .and_then(|key| u8::from_str_radix(key, 16).ok())
+        .filter(|&key| key < 16)
+        .ok_or_else(|| format!("Invalid key in {s}"))?;
+    let frames = parts.next().map(parse_number).transpose()?.unwrap_or(1);
+    if frames == 0 {
+        return Err(format!("Key in {s} must be held for at least one frame"));
+    }
+
+    Ok(KeyPress { frame, key, frames })
+}
+
+fn output(path: &str, data: &[u8]) -> std::io::Result<()> {
+    if path == "-" {
+        use std::io::Write;
+        std::io::stdout().write_all(data)
+    } else {
+        fs::write(path, data)
+    }
+}
+
+fn screen_png(chip8: &Chip8) -> Vec<u8> {
+    let pixels: Vec<_> = chip8.framebuffer()[..chip8.height()]
+        .iter()
+        .flat_map(|row| &row[..chip8.width()])
+        .map(|&pixel| PNG_PALETTE[pixel as usize & 0b11])
+        .collect();
+    png::encode(chip8.width() as u32, chip8.height() as u32, &pixels)
+}
+
+fn registers_json(chip8: &Chip8, fault: Option<&Chip8Error>) -> String {
+    let list = |values: Vec<String>| format!("[{}]", values.join(", "));
+    let v = list(chip8.v().iter().map(u8::to_string).collect());
+    let stack = list(chip8.stack().iter().map(usize::to_string).collect());
+    let fault = fault.map_or("null".to_string(), |fault| format!("{:?}", fault.to_string()));
+
+    format!(
+        "{{\n  \"v\": {v},\n  \"i\": {},\n  \"pc\": {},\n  \"sp\": {},\n  \"stack\": {stack},\n  \
+         \"delay\": {},\n  \"sound\": {},\n  \"exited\": {},\n  \"fault\": {fault}\n}}\n",
+        chip8.i(),
+        chip8.pc(),
+        chip8.sp(),
+        chip8.delay(),
+        chip8.sound(),
+        chip8.exited(),
+    )
+}
@@ -0,0 +1,121 @@
+use std::{fmt, ops::RangeInclusive};
+
+use crate::symbols::Symbols;
+
+/// Register watched by [`Breakpoint::Register`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+}
+
+/// Condition that stops execution, see [`Chip8::add_breakpoint`].
+///
+/// [`Chip8::add_breakpoint`]: crate::Chip8::add_breakpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The next instruction is at this address
+    Pc(usize),
+    /// The next instruction matches `value` in the bits set in `mask`
+    Opcode { mask: u16, value: u16 },
+    /// An instruction read data from an address in the range
+    Read(RangeInclusive<usize>),
+    /// An instruction wrote to an address in the range
+    Write(RangeInclusive<usize>),
+    /// An instruction changed the value of the register
+    Register(Register),
+}
+
+impl Breakpoint {
+    /// Parses a breakpoint from the command line, one of `<addr>`,
+    /// `op:<pattern>`, `read:<addr>[-<addr>]`, `write:<addr>[-<addr>]` or
+    /// `reg:<V0..VF|I>`, with addresses given as a label from `symbols` or
+    /// in hex with a `0x` prefix
+    pub fn from_spec(spec: &str, symbols: &Symbols) -> Option<Self> {
+        let Some((kind, arg)) = spec.split_once(':') else {
+            return symbols.resolve(spec).map(Self::Pc);
+        };
+        match kind {
+            "op" => Self::opcode(arg),
+            "read" => symbols.resolve_range(arg).map(Self::Read),
+            "write" => symbols.resolve_range(arg).map(Self::Write),
+            "reg" => match arg.to_ascii_uppercase().as_str() {
+                "I" => Some(Self::Register(Register::I)),
+                reg => {
+                    let x = u8::from_str_radix(reg.strip_prefix('V')?, 16).ok()?;
+                    (x < 16).then_some(Self::Register(Register::V(x)))
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses an opcode pattern such as `DXYN` or `8XY6`, where hex digits
+    /// must match and any other letter matches anything
+    pub fn opcode(pattern: &str) -> Option<Self> {
+        if pattern.chars().count() != 4 {
+            return None;
+        }
+
+        let mut mask = 0;
+        let mut value = 0;
+        for c in pattern.chars() {
+            mask <<= 4;
+            value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                mask |= 0xf;
+                value |= digit as u16;
+            } else if !c.is_ascii_alphabetic() {
+                return None;
+            }
+        }
+        Some(Self::Opcode { mask, value })
+    }
+}
+
+/// Why [`Chip8::step`] stopped, reported after the instruction at `pc`
+/// executed.
+///
+/// [`Chip8::step`]: crate::Chip8::step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Break {
+    /// The next instruction is at a PC breakpoint
+    Pc(usize),
+    /// The next instruction matches an opcode breakpoint
+    Opcode { pc: usize, op: u16 },
+    Read { pc: usize, addr: usize },
+    Write { pc: usize, addr: usize },
+    Register { pc: usize, register: Register, old: usize, new: usize },
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V(x) => write!(f, "V{x:X}"),
+            Self::I => write!(f, "I"),
+        }
+    }
+}
+
+impl Break {
+    /// Like the [`Display`](fmt::Display) output, with addresses labeled
+    /// from `symbols`
+    pub fn describe(&self, symbols: &Symbols) -> String {
+        let at = |addr| symbols.describe(addr);
+        match *self {
+            Self::Pc(pc) => format!("breakpoint at {}", at(pc)),
+            Self::Opcode { pc, op } => format!("opcode breakpoint on {op:#06x} at {}", at(pc)),
+            Self::Read { pc, addr } => format!("{} read watched address {}", at(pc), at(addr)),
+            Self::Write { pc, addr } => format!("{} wrote watched address {}", at(pc), at(addr)),
+            Self::Register { pc, register, old, new } => {
+                format!("{} changed {register} from {old:#x} to {new:#x}", at(pc))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(&Symbols::new()))
+    }
+}
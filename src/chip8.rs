@@ -1,12 +1,22 @@
-use rand::Rng;
+use crate::{
+    breakpoint::{Break, Breakpoint},
+    disasm::Instruction,
+    error::{Chip8Error, Fault},
+    quirks::{IndexIncrement, Quirks},
+    rng::Rng,
+    trace::Tracer,
+};
 
-use crate::time::Timer;
+mod debug;
+mod state;
 
 pub const CHIP8_WIDTH: usize = 64;
 pub const CHIP8_HEIGHT: usize = 32;
-pub const CHIP8_MEM: usize = 4096;
-pub const ONE_BY_CLOCK_SPEED: f32 = 1.0 / 500.0;
-pub const ONE_BY_FPS: f32 = 1.0 / 60.0;
+pub const CHIP8_HIRES_WIDTH: usize = 128;
+pub const CHIP8_HIRES_HEIGHT: usize = 64;
+pub const CHIP8_MEM: usize = 0x10000;
+/// Instructions per 60Hz frame giving roughly the classic 500Hz clock
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 8;
 pub const CHIP8_FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -25,7 +35,32 @@ pub const CHIP8_FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+pub const CHIP8_BIG_FONTSET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
 const OP_SIZE: usize = 2;
+const PROGRAM_START: usize = 0x200;
+const BIG_FONT_START: usize = CHIP8_FONTSET.len();
+/// Characters of [`Chip8::screen_text`] for each pixel value
+const TEXT_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+/// Square wave played for plain CHIP-8 and SCHIP ROMs, which never load a pattern
+const DEFAULT_AUDIO_PATTERN: [u8; 16] = [0xf0; 16];
+const DEFAULT_PITCH: u8 = 64;
 
 enum ProgramCounterState {
     Next,
@@ -33,6 +68,8 @@ enum ProgramCounterState {
     Jmp(usize),
 }
 
+type OpResult = Result<ProgramCounterState, Fault>;
+
 #[derive(Debug)]
 pub struct Chip8 {
     v: [u8; 16],
@@ -41,307 +78,669 @@ pub struct Chip8 {
     sp: usize,
     stack: [usize; 16],
     mem: [u8; CHIP8_MEM],
-    pub screen: [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
-    pub draw_flag: bool,
-    pub beep: bool,
+    screen: [[u8; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT],
+    hires: bool,
+    rpl: [u8; 16],
+    exited: bool,
+    planes: u8,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    draw_flag: bool,
     delay: u8,
     sound: u8,
     keypad: [bool; 16],
     last_key: Option<u8>,
     waiting_key: bool,
+    vblank_wait: bool,
+    rng: Rng,
+    quirks: Quirks,
+    breakpoints: Vec<Breakpoint>,
+    /// First watched memory access by the instruction being executed
+    watch_hit: Option<Break>,
+    /// Whether the breakpoints were checked against the first instruction,
+    /// which no earlier instruction stopped in front of
+    entry_checked: bool,
+    tracer: Option<Tracer>,
 }
 
 impl Chip8 {
-    pub fn new(content: Vec<u8>) -> Self {
+    pub fn new(content: Vec<u8>, quirks: Quirks) -> Result<Self, Chip8Error> {
+        let max = CHIP8_MEM - PROGRAM_START;
+        if content.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: content.len(), max });
+        }
+
+        let mut chip8 = Self { quirks, ..Self::blank() };
+        chip8.mem[PROGRAM_START..content.len() + PROGRAM_START].copy_from_slice(&content);
+
+        Ok(chip8)
+    }
+
+    /// Machine in its power-on state, with the fonts loaded and no ROM
+    fn blank() -> Self {
         let mut ram = [0; CHIP8_MEM];
         ram[..CHIP8_FONTSET.len()].copy_from_slice(&CHIP8_FONTSET[..]);
-        ram[0x200..content.len() + 0x200].copy_from_slice(&content);
+        ram[BIG_FONT_START..BIG_FONT_START + CHIP8_BIG_FONTSET.len()]
+            .copy_from_slice(&CHIP8_BIG_FONTSET[..]);
 
         Self {
             v: [0; 16],
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             sp: 0,
             stack: [0; 16],
             mem: ram,
-            screen: [[0; CHIP8_WIDTH]; CHIP8_HEIGHT],
+            screen: [[0; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT],
+            hires: false,
+            rpl: [0; 16],
+            exited: false,
+            planes: 1,
+            audio_pattern: DEFAULT_AUDIO_PATTERN,
+            pitch: DEFAULT_PITCH,
             delay: 0,
             sound: 0,
             keypad: [false; 16],
             last_key: None,
             waiting_key: false,
+            vblank_wait: false,
+            rng: Rng::from_entropy(),
+            quirks: Quirks::default(),
             draw_flag: false,
-            beep: false,
+            breakpoints: vec![],
+            watch_hit: None,
+            entry_checked: false,
+            tracer: None,
         }
     }
 
-    pub fn tick(&mut self, timer: &mut Timer, clock_timer: &mut Timer) {
-        if clock_timer.acc >= ONE_BY_CLOCK_SPEED {
-            let op = self.get_op();
-            self.exec_op(op);
-            clock_timer.reset();
+    /// Fetches and executes a single instruction, returning the breakpoint
+    /// it triggered, if any. The first call only stops without executing
+    /// anything if a breakpoint is set on the first instruction.
+    ///
+    /// Does nothing once the ROM has exited, or while a draw is waiting for
+    /// the next timer tick under the display wait quirk.
+    pub fn step(&mut self) -> Result<Option<Break>, Chip8Error> {
+        if self.vblank_wait || self.exited {
+            return Ok(None);
         }
-
-        if timer.acc >= ONE_BY_FPS {
-            if self.sound > 0 {
-                self.sound -= 1;
-            }
-            if self.delay > 0 {
-                self.delay -= 1;
+        if !self.entry_checked {
+            self.entry_checked = true;
+            if let Some(hit) = self.check_entry() {
+                return Ok(Some(hit));
             }
+        }
+
+        let op = self.get_op()?;
+        let pc = self.pc;
+        let (v, i) = (self.v, self.i);
+        self.watch_hit = None;
+        let result = self.exec_op(op).map_err(|fault| fault.at(pc, op));
+        if self.tracer.is_some() {
+            self.trace(pc, op, v, i, result.as_ref().err());
+        }
+        result?;
+
+        if self.breakpoints.is_empty() {
+            return Ok(None);
+        }
+        Ok(self.check_breakpoints(pc, v, i))
+    }
+
+    /// Decrements the delay and sound timers, to be called at 60Hz
+    pub fn tick_timers(&mut self) {
+        if self.sound > 0 {
+            self.sound -= 1;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+        }
+
+        self.vblank_wait = false;
+    }
+
+    /// Whether a draw is waiting for the next timer tick under the display
+    /// wait quirk, so [`Chip8::step`] does nothing until then
+    pub fn waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
+
+    /// Whether the buzzer should currently be sounding
+    pub fn sound_active(&self) -> bool {
+        self.sound > 0
+    }
+
+    /// Pixels of the display, of which the top-left `width()` by `height()`
+    /// are in use. Each value has bit 0 set for plane 1 and bit 1 for plane 2.
+    pub fn framebuffer(&self) -> &[[u8; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT] {
+        &self.screen
+    }
+
+    /// The display in use as text, one line per row with `.` for unlit
+    /// pixels and `#`, `+` and `@` for pixels lit in plane 1, plane 2 and
+    /// both planes
+    pub fn screen_text(&self) -> String {
+        let mut out = String::new();
+        for row in &self.screen[..self.height()] {
+            out.extend(row[..self.width()].iter().map(|&pixel| TEXT_PIXELS[pixel as usize & 0b11]));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Returns whether the framebuffer changed since the last call
+    pub fn take_draw_flag(&mut self) -> bool {
+        std::mem::take(&mut self.draw_flag)
+    }
+
+    /// Width of the display in the current resolution
+    pub fn width(&self) -> usize {
+        if self.hires { CHIP8_HIRES_WIDTH } else { CHIP8_WIDTH }
+    }
+
+    /// Height of the display in the current resolution
+    pub fn height(&self) -> usize {
+        if self.hires { CHIP8_HIRES_HEIGHT } else { CHIP8_HEIGHT }
+    }
+
+    /// Whether the ROM has executed `00FD`
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// General purpose registers V0 to VF
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    /// Index register
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    /// Address of the next instruction
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Number of return addresses on the stack
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    pub fn sound(&self) -> u8 {
+        self.sound
+    }
+
+    /// The whole address space, including the font and the loaded ROM
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Mutable access to the address space, for debuggers editing memory
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+
+    /// Keys currently held down, indexed by key
+    pub fn keypad(&self) -> &[bool; 16] {
+        &self.keypad
+    }
+
+    /// Generator behind `CXNN`, seeded from the OS unless replaced
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// The 128 1-bit samples making up the current sound
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// Rate in samples per second at which the audio pattern is played
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Marks key `0x0..=0xF` as held down
+    pub fn press_key(&mut self, key: u8) {
+        self.keypad[(key & 0xf) as usize] = true;
+    }
+
+    /// Marks key `0x0..=0xF` as released, completing a pending `FX0A`
+    pub fn release_key(&mut self, key: u8) {
+        let key = key & 0xf;
+        if self.waiting_key {
+            self.last_key = Some(key);
+        }
+
+        self.keypad[key as usize] = false;
+    }
+
+    fn get_op(&self) -> Result<u16, Chip8Error> {
+        let fetch = |addr| self.fetch(addr).map_err(|fault| fault.at(self.pc, 0));
+        Ok((fetch(self.pc)? as u16) << 8 | (fetch(self.pc + 1)? as u16))
+    }
+
+    fn peek_op(&self) -> Option<u16> {
+        let hi = *self.mem.get(self.pc)?;
+        let lo = *self.mem.get(self.pc + 1)?;
+        Some((hi as u16) << 8 | lo as u16)
+    }
+
+    /// Reads code, which watchpoints do not apply to
+    fn fetch(&self, addr: usize) -> Result<u8, Fault> {
+        self.mem.get(addr).copied().ok_or(Fault::MemoryOutOfBounds(addr))
+    }
+
+    fn read(&mut self, addr: usize) -> Result<u8, Fault> {
+        let val = self.fetch(addr)?;
+        self.watch(addr, false);
+        Ok(val)
+    }
+
+    fn write(&mut self, addr: usize, val: u8) -> Result<(), Fault> {
+        let byte = self.mem.get_mut(addr).ok_or(Fault::MemoryOutOfBounds(addr))?;
+        *byte = val;
+        self.watch(addr, true);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.writes.push((addr, val));
+        }
+        Ok(())
+    }
+
+    fn exec_op(&mut self, op: u16) -> Result<(), Fault> {
+        use Instruction::*;
+
+        let instruction = Instruction::decode(op).ok_or(Fault::InvalidOpcode)?;
+        let reg = |x: u8| x as usize;
 
-            self.beep = self.sound > 0;
-            timer.reset();
-        }
-    }
-
-    pub fn update_keypad(&mut self, code: u8, pressed: bool) {
-        if self.waiting_key && !pressed {
-            self.last_key = Some(code);
-        }
-
-        self.keypad[code as usize] = pressed;
-    }
-
-    fn get_op(&self) -> u16 {
-        (self.mem[self.pc] as u16) << 8 | (self.mem[self.pc + 1] as u16)
-    }
-
-    fn exec_op(&mut self, op: u16) {
-        let first = (op >> 12) as u8;
-        let second = ((op >> 8) & 0xf) as u8;
-        let third = ((op >> 4) & 0xf) as u8;
-        let fourth = (op & 0xf) as u8;
-
-        let nnn = (op & 0xfff) as usize;
-        let kk = (op & 0xff) as u8;
-        let x = second as usize;
-        let y = third as usize;
-        let n = fourth as usize;
-
-        let pc_state = match (first, second, third, fourth) {
-            (0x0, 0x0, 0xe, 0x0) => self.clear_display(),
-            (0x0, 0x0, 0xe, 0xe) => self.ret(),
-            (0x1, _, _, _) => self.jmp(nnn),
-            (0x2, _, _, _) => self.call(nnn),
-            (0x3, _, _, _) => self.skip_kk_eq(x, kk),
-            (0x4, _, _, _) => self.skip_kk_ne(x, kk),
-            (0x5, _, _, 0x0) => self.skip_vy_eq(x, y),
-            (0x6, _, _, _) => self.load_kk(x, kk),
-            (0x7, _, _, _) => self.add_kk(x, kk),
-            (0x8, _, _, 0x0) => self.load_vy(x, y),
-            (0x8, _, _, 0x1) => self.or(x, y),
-            (0x8, _, _, 0x2) => self.and(x, y),
-            (0x8, _, _, 0x3) => self.xor(x, y),
-            (0x8, _, _, 0x4) => self.add_vy(x, y),
-            (0x8, _, _, 0x5) => self.sub(x, y),
-            (0x8, _, _, 0x6) => self.shr(x, y),
-            (0x8, _, _, 0x7) => self.subn(x, y),
-            (0x8, _, _, 0xe) => self.shl(x, y),
-            (0x9, _, _, 0x0) => self.skip_vy_ne(x, y),
-            (0xa, _, _, _) => self.load_addr(nnn),
-            (0xb, _, _, _) => self.jmp(nnn + self.v[0] as usize),
-            (0xc, _, _, _) => self.rand(x, kk),
-            (0xd, _, _, _) => self.draw(x, y, n),
-            (0xe, _, 0x9, 0xe) => self.skip_key_eq(x),
-            (0xe, _, 0xa, 0x1) => self.skip_key_ne(x),
-            (0xf, _, 0x0, 0x7) => self.load_delay(x),
-            (0xf, _, 0x0, 0xa) => self.load_key(x),
-            (0xf, _, 0x1, 0x5) => self.load_vx_delay(x),
-            (0xf, _, 0x1, 0x8) => self.load_vx_sound(x),
-            (0xf, _, 0x1, 0xe) => self.add_i(x),
-            (0xf, _, 0x2, 0x9) => self.load_sprite(x),
-            (0xf, _, 0x3, 0x3) => self.load_bcd(x),
-            (0xf, _, 0x5, 0x5) => self.store_v0_vx(x),
-            (0xf, _, 0x6, 0x5) => self.load_v0_vx(x),
-            _ => panic!("Invalid instruction: {op:#x}"),
-        };
+        let pc_state = match instruction {
+            ScrollDown(n) => self.scroll_down(n as usize),
+            Clear => self.clear_display(),
+            Return => self.ret(),
+            ScrollRight => self.scroll_right(),
+            ScrollLeft => self.scroll_left(),
+            Exit => self.exit(),
+            Lores => self.set_hires(false),
+            Hires => self.set_hires(true),
+            Jump(nnn) => self.jmp(nnn as usize),
+            Call(nnn) => self.call(nnn as usize),
+            SkipEqImm { x, kk } => self.skip_kk_eq(reg(x), kk),
+            SkipNeImm { x, kk } => self.skip_kk_ne(reg(x), kk),
+            SkipEq { x, y } => self.skip_vy_eq(reg(x), reg(y)),
+            SaveRange { x, y } => self.store_vx_vy(reg(x), reg(y)),
+            LoadRange { x, y } => self.load_vx_vy(reg(x), reg(y)),
+            LoadImm { x, kk } => self.load_kk(reg(x), kk),
+            AddImm { x, kk } => self.add_kk(reg(x), kk),
+            Move { x, y } => self.load_vy(reg(x), reg(y)),
+            Or { x, y } => self.or(reg(x), reg(y)),
+            And { x, y } => self.and(reg(x), reg(y)),
+            Xor { x, y } => self.xor(reg(x), reg(y)),
+            Add { x, y } => self.add_vy(reg(x), reg(y)),
+            Sub { x, y } => self.sub(reg(x), reg(y)),
+            Shr { x, y } => self.shr(reg(x), reg(y)),
+            SubN { x, y } => self.subn(reg(x), reg(y)),
+            Shl { x, y } => self.shl(reg(x), reg(y)),
+            SkipNe { x, y } => self.skip_vy_ne(reg(x), reg(y)),
+            LoadI(nnn) => self.load_addr(nnn as usize),
+            JumpOffset { x, nnn } => self.jmp_offset(reg(x), nnn as usize),
+            Random { x, kk } => self.rand(reg(x), kk),
+            Draw { x, y, n } => self.draw(reg(x), reg(y), n as usize),
+            SkipKey(x) => self.skip_key_eq(reg(x)),
+            SkipNotKey(x) => self.skip_key_ne(reg(x)),
+            LoadLongI => self.load_long_addr(),
+            Plane(x) => self.select_planes(reg(x)),
+            Audio => self.load_audio_pattern(),
+            GetDelay(x) => self.load_delay(reg(x)),
+            WaitKey(x) => self.load_key(reg(x)),
+            SetDelay(x) => self.load_vx_delay(reg(x)),
+            SetSound(x) => self.load_vx_sound(reg(x)),
+            AddI(x) => self.add_i(reg(x)),
+            Font(x) => self.load_sprite(reg(x)),
+            BigFont(x) => self.load_big_sprite(reg(x)),
+            Bcd(x) => self.load_bcd(reg(x)),
+            Pitch(x) => self.load_pitch(reg(x)),
+            Save(x) => self.store_v0_vx(reg(x)),
+            Load(x) => self.load_v0_vx(reg(x)),
+            SaveFlags(x) => self.store_rpl(reg(x)),
+            LoadFlags(x) => self.load_rpl(reg(x)),
+        }?;
 
         match pc_state {
             ProgramCounterState::Next => self.pc += OP_SIZE,
-            ProgramCounterState::Skip => self.pc += 2 * OP_SIZE,
+            ProgramCounterState::Skip => {
+                // The skipped instruction may be the 4 byte F000 NNNN
+                self.pc += OP_SIZE;
+                let skipped = self.peek_op().and_then(Instruction::decode);
+                self.pc += skipped.map_or(OP_SIZE, |instruction| instruction.size());
+            }
             ProgramCounterState::Jmp(addr) => self.pc = addr,
         }
+
+        Ok(())
+    }
+
+    fn clear_display(&mut self) -> OpResult {
+        for row in self.screen.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.planes;
+            }
+        }
+        self.draw_flag = true;
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn scroll_down(&mut self, n: usize) -> OpResult {
+        self.scroll(0, n as isize);
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn scroll_right(&mut self) -> OpResult {
+        self.scroll(4, 0);
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn scroll_left(&mut self) -> OpResult {
+        self.scroll(-4, 0);
+        Ok(ProgramCounterState::Next)
+    }
+
+    /// Shifts the selected planes by (dx, dy), filling the gap with blank pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.width() as isize;
+        let height = self.height() as isize;
+        let old = self.screen;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[src_y as usize][src_x as usize] & self.planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.screen[y as usize][x as usize];
+                *pixel = (*pixel & !self.planes) | moved;
+            }
+        }
+
+        self.draw_flag = true;
+    }
+
+    fn exit(&mut self) -> OpResult {
+        self.exited = true;
+        Ok(ProgramCounterState::Next)
     }
 
-    fn clear_display(&mut self) -> ProgramCounterState {
-        self.screen = [[0; CHIP8_WIDTH]; CHIP8_HEIGHT];
-        ProgramCounterState::Next
+    fn set_hires(&mut self, hires: bool) -> OpResult {
+        self.hires = hires;
+        self.screen = [[0; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT];
+        self.draw_flag = true;
+        Ok(ProgramCounterState::Next)
     }
 
-    fn ret(&mut self) -> ProgramCounterState {
+    fn ret(&mut self) -> OpResult {
+        if self.sp == 0 {
+            return Err(Fault::StackUnderflow);
+        }
         self.sp -= 1;
-        ProgramCounterState::Jmp(self.stack[self.sp])
+        Ok(ProgramCounterState::Jmp(self.stack[self.sp]))
     }
 
-    fn jmp(&mut self, nnn: usize) -> ProgramCounterState {
-        ProgramCounterState::Jmp(nnn)
+    fn jmp(&mut self, nnn: usize) -> OpResult {
+        Ok(ProgramCounterState::Jmp(nnn))
     }
 
-    fn call(&mut self, nnn: usize) -> ProgramCounterState {
+    fn jmp_offset(&mut self, x: usize, nnn: usize) -> OpResult {
+        let offset = if self.quirks.jump_vx { self.v[x] } else { self.v[0] };
+        Ok(ProgramCounterState::Jmp(nnn + offset as usize))
+    }
+
+    fn call(&mut self, nnn: usize) -> OpResult {
+        if self.sp == self.stack.len() {
+            return Err(Fault::StackOverflow);
+        }
         self.stack[self.sp] = self.pc + OP_SIZE; // jmp to next instruction
         self.sp += 1;
-        ProgramCounterState::Jmp(nnn)
+        Ok(ProgramCounterState::Jmp(nnn))
     }
 
-    fn skip_kk_eq(&mut self, x: usize, kk: u8) -> ProgramCounterState {
+    fn skip_kk_eq(&mut self, x: usize, kk: u8) -> OpResult {
         if self.v[x] == kk {
-            return ProgramCounterState::Skip;
+            return Ok(ProgramCounterState::Skip);
         }
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn skip_kk_ne(&mut self, x: usize, kk: u8) -> ProgramCounterState {
+    fn skip_kk_ne(&mut self, x: usize, kk: u8) -> OpResult {
         if self.v[x] != kk {
-            return ProgramCounterState::Skip;
+            return Ok(ProgramCounterState::Skip);
         }
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn skip_vy_eq(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn skip_vy_eq(&mut self, x: usize, y: usize) -> OpResult {
         if self.v[x] == self.v[y] {
-            return ProgramCounterState::Skip;
+            return Ok(ProgramCounterState::Skip);
         }
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_kk(&mut self, x: usize, kk: u8) -> ProgramCounterState {
+    fn load_kk(&mut self, x: usize, kk: u8) -> OpResult {
         self.v[x] = kk;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn add_kk(&mut self, x: usize, kk: u8) -> ProgramCounterState {
+    fn add_kk(&mut self, x: usize, kk: u8) -> OpResult {
         self.v[x] = self.v[x].wrapping_add(kk);
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn store_vx_vy(&mut self, x: usize, y: usize) -> OpResult {
+        // The range is walked backwards when X > Y
+        for (offset, reg) in register_range(x, y).enumerate() {
+            self.write(self.i + offset, self.v[reg])?;
+        }
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_vy(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn load_vx_vy(&mut self, x: usize, y: usize) -> OpResult {
+        for (offset, reg) in register_range(x, y).enumerate() {
+            self.v[reg] = self.read(self.i + offset)?;
+        }
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn load_vy(&mut self, x: usize, y: usize) -> OpResult {
         self.v[x] = self.v[y];
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn or(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn or(&mut self, x: usize, y: usize) -> OpResult {
         self.v[x] |= self.v[y];
-        self.v[0xf] = 0;
-        ProgramCounterState::Next
+        if self.quirks.vf_reset {
+            self.v[0xf] = 0;
+        }
+        Ok(ProgramCounterState::Next)
     }
 
-    fn and(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn and(&mut self, x: usize, y: usize) -> OpResult {
         self.v[x] &= self.v[y];
-        self.v[0xf] = 0;
-        ProgramCounterState::Next
+        if self.quirks.vf_reset {
+            self.v[0xf] = 0;
+        }
+        Ok(ProgramCounterState::Next)
     }
 
-    fn xor(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn xor(&mut self, x: usize, y: usize) -> OpResult {
         self.v[x] ^= self.v[y];
-        self.v[0xf] = 0;
-        ProgramCounterState::Next
+        if self.quirks.vf_reset {
+            self.v[0xf] = 0;
+        }
+        Ok(ProgramCounterState::Next)
     }
 
-    fn add_vy(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn add_vy(&mut self, x: usize, y: usize) -> OpResult {
         let result = self.v[x] as u16 + self.v[y] as u16;
         self.v[x] = result as u8;
         self.v[0xf] = (result > 0xff) as u8;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn sub(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn sub(&mut self, x: usize, y: usize) -> OpResult {
         let result = self.v[x] as i16 - self.v[y] as i16;
         self.v[x] = result as u8;
         self.v[0xf] = (result >= 0) as u8;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn shr(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn shr(&mut self, x: usize, y: usize) -> OpResult {
+        let y = if self.quirks.shift_vx { x } else { y };
         let original = self.v[y];
         self.v[x] = self.v[y] >> 1;
         self.v[0xf] = original & 1;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn subn(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn subn(&mut self, x: usize, y: usize) -> OpResult {
         let result = self.v[y] as i16 - self.v[x] as i16;
         self.v[x] = result as u8;
         self.v[0xf] = (result >= 0) as u8;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn shl(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn shl(&mut self, x: usize, y: usize) -> OpResult {
+        let y = if self.quirks.shift_vx { x } else { y };
         let original = self.v[y];
         self.v[x] = self.v[y] << 1;
         self.v[0xf] = (original & 0b10000000) >> 7;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn skip_vy_ne(&mut self, x: usize, y: usize) -> ProgramCounterState {
+    fn skip_vy_ne(&mut self, x: usize, y: usize) -> OpResult {
         if self.v[x] != self.v[y] {
-            return ProgramCounterState::Skip;
+            return Ok(ProgramCounterState::Skip);
         }
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_addr(&mut self, nnn: usize) -> ProgramCounterState {
+    fn load_addr(&mut self, nnn: usize) -> OpResult {
         self.i = nnn;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn rand(&mut self, x: usize, kk: u8) -> ProgramCounterState {
-        let num: u8 = rand::rng().random();
+    fn rand(&mut self, x: usize, kk: u8) -> OpResult {
+        let num = self.rng.next_byte();
         self.v[x] = num & kk;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn draw(&mut self, x: usize, y: usize, n: usize) -> ProgramCounterState {
-        let x = (self.v[x] as usize) % CHIP8_WIDTH;
-        let y = (self.v[y] as usize) % CHIP8_HEIGHT;
+    fn draw(&mut self, x: usize, y: usize, n: usize) -> OpResult {
+        // DXY0 draws a 16x16 sprite made of two bytes per row
+        let (rows, cols) = if n == 0 { (16, 16) } else { (n, 8) };
+        let sprite_len = rows * cols / 8;
+        let width = self.width();
+        let height = self.height();
+        let x = (self.v[x] as usize) % width;
+        let y = (self.v[y] as usize) % height;
         self.v[0xf] = 0;
 
-        'outer: for i in 0..n {
-            let pixel = self.mem[self.i + i];
-            for j in 0..8 {
-                let val = (pixel >> (7 - j)) & 0b1;
-                let x = x + j;
-                let y = y + i;
-                if y >= CHIP8_HEIGHT {
-                    break 'outer;
-                }
-                if x >= CHIP8_WIDTH {
-                    continue;
+        // Each selected plane consumes its own sprite, stored one after the other
+        let mut addr = self.i;
+        for plane in [0b01, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+
+            'outer: for i in 0..rows {
+                for j in 0..cols {
+                    let pixel = self.read(addr + i * cols / 8 + j / 8)?;
+                    let val = ((pixel >> (7 - j % 8)) & 0b1) * plane;
+                    let mut x = x + j;
+                    let mut y = y + i;
+                    if self.quirks.clip_sprites {
+                        if y >= height {
+                            break 'outer;
+                        }
+                        if x >= width {
+                            continue;
+                        }
+                    } else {
+                        x %= width;
+                        y %= height;
+                    }
+                    if val & self.screen[y][x] != 0 {
+                        self.v[0xf] = 1;
+                    }
+                    self.screen[y][x] ^= val;
                 }
-                self.v[0xf] |= val & self.screen[y][x];
-                self.screen[y][x] ^= val;
             }
+
+            addr += sprite_len;
         }
 
         self.draw_flag = true;
+        self.vblank_wait = self.quirks.display_wait;
+
+        Ok(ProgramCounterState::Next)
+    }
 
-        ProgramCounterState::Next
+    fn skip_key_eq(&mut self, x: usize) -> OpResult {
+        if self.keypad[(self.v[x] & 0xf) as usize] {
+            return Ok(ProgramCounterState::Skip);
+        }
+        Ok(ProgramCounterState::Next)
     }
 
-    fn skip_key_eq(&mut self, x: usize) -> ProgramCounterState {
-        if self.keypad[self.v[x] as usize] {
-            return ProgramCounterState::Skip;
+    fn skip_key_ne(&mut self, x: usize) -> OpResult {
+        if !self.keypad[(self.v[x] & 0xf) as usize] {
+            return Ok(ProgramCounterState::Skip);
         }
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn load_long_addr(&mut self) -> OpResult {
+        let hi = self.fetch(self.pc + 2)? as usize;
+        let lo = self.fetch(self.pc + 3)? as usize;
+        self.i = hi << 8 | lo;
+        Ok(ProgramCounterState::Jmp(self.pc + 2 * OP_SIZE))
     }
 
-    fn skip_key_ne(&mut self, x: usize) -> ProgramCounterState {
-        if !self.keypad[self.v[x] as usize] {
-            return ProgramCounterState::Skip;
+    fn select_planes(&mut self, x: usize) -> OpResult {
+        self.planes = x as u8 & 0b11;
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn load_audio_pattern(&mut self) -> OpResult {
+        for i in 0..self.audio_pattern.len() {
+            self.audio_pattern[i] = self.read(self.i + i)?;
         }
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_delay(&mut self, x: usize) -> ProgramCounterState {
+    fn load_delay(&mut self, x: usize) -> OpResult {
         self.v[x] = self.delay;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_key(&mut self, x: usize) -> ProgramCounterState {
+    fn load_key(&mut self, x: usize) -> OpResult {
         self.waiting_key = true;
-        if let None = self.last_key {
+        if self.last_key.is_none() {
             self.pc -= 2; // rerun this
-            return ProgramCounterState::Next;
+            return Ok(ProgramCounterState::Next);
         }
 
         let code = self.last_key.unwrap();
@@ -349,50 +748,352 @@ impl Chip8 {
         self.last_key = None;
         self.v[x] = code;
 
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_vx_delay(&mut self, x: usize) -> ProgramCounterState {
+    fn load_vx_delay(&mut self, x: usize) -> OpResult {
         self.delay = self.v[x];
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_vx_sound(&mut self, x: usize) -> ProgramCounterState {
+    fn load_vx_sound(&mut self, x: usize) -> OpResult {
         self.sound = self.v[x];
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn add_i(&mut self, x: usize) -> ProgramCounterState {
+    fn add_i(&mut self, x: usize) -> OpResult {
         self.i += self.v[x] as usize;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_sprite(&mut self, x: usize) -> ProgramCounterState {
+    fn load_sprite(&mut self, x: usize) -> OpResult {
         self.i = (self.v[x] as usize) * 5;
-        ProgramCounterState::Next
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn load_big_sprite(&mut self, x: usize) -> OpResult {
+        self.i = BIG_FONT_START + ((self.v[x] & 0xf) as usize) * 10;
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn load_pitch(&mut self, x: usize) -> OpResult {
+        self.pitch = self.v[x];
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_bcd(&mut self, x: usize) -> ProgramCounterState {
+    fn load_bcd(&mut self, x: usize) -> OpResult {
         let num = self.v[x];
-        self.mem[self.i] = num / 100;
-        self.mem[self.i + 1] = (num / 10) % 10;
-        self.mem[self.i + 2] = num % 10;
-        ProgramCounterState::Next
+        self.write(self.i, num / 100)?;
+        self.write(self.i + 1, (num / 10) % 10)?;
+        self.write(self.i + 2, num % 10)?;
+        Ok(ProgramCounterState::Next)
     }
 
-    fn store_v0_vx(&mut self, x: usize) -> ProgramCounterState {
+    fn store_v0_vx(&mut self, x: usize) -> OpResult {
         for i in 0..x + 1 {
-            self.mem[self.i] = self.v[i];
-            self.i += 1;
+            self.write(self.i + i, self.v[i])?;
         }
-        ProgramCounterState::Next
+        self.increment_index(x);
+        Ok(ProgramCounterState::Next)
     }
 
-    fn load_v0_vx(&mut self, x: usize) -> ProgramCounterState {
+    fn load_v0_vx(&mut self, x: usize) -> OpResult {
         for i in 0..x + 1 {
-            self.v[i] = self.mem[self.i];
-            self.i += 1;
+            self.v[i] = self.read(self.i + i)?;
+        }
+        self.increment_index(x);
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn store_rpl(&mut self, x: usize) -> OpResult {
+        self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn load_rpl(&mut self, x: usize) -> OpResult {
+        self.v[..=x].copy_from_slice(&self.rpl[..=x]);
+        Ok(ProgramCounterState::Next)
+    }
+
+    fn increment_index(&mut self, x: usize) {
+        match self.quirks.index_increment {
+            IndexIncrement::XPlusOne => self.i += x + 1,
+            IndexIncrement::X => self.i += x,
+            IndexIncrement::Unchanged => {}
+        }
+    }
+}
+
+/// Registers from VX to VY inclusive, in the order `5XY2`/`5XY3` visit them
+fn register_range(x: usize, y: usize) -> impl Iterator<Item = usize> {
+    (0..=x.abs_diff(y)).map(move |offset| if x <= y { x + offset } else { x - offset })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `program` and executes `steps` instructions of it
+    fn execute(program: &[u8], quirks: Quirks, steps: usize) -> Chip8 {
+        let mut chip8 = Chip8::new(program.to_vec(), quirks).unwrap();
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+        chip8
+    }
+
+    /// Coordinates of every lit pixel, row by row
+    fn lit(chip8: &Chip8) -> Vec<(usize, usize)> {
+        let mut pixels = vec![];
+        for (y, row) in chip8.framebuffer()[..chip8.height()].iter().enumerate() {
+            for (x, &pixel) in row[..chip8.width()].iter().enumerate() {
+                if pixel != 0 {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn vf_reset() {
+        let program = [
+            0x60, 0x0f, // V0 = 0x0F
+            0x6f, 0x01, // VF = 1
+            0x80, 0x11, // V0 |= V1
+        ];
+        assert_eq!(execute(&program, Quirks::COSMAC_VIP, 3).v()[0xf], 0);
+        assert_eq!(execute(&program, Quirks::SCHIP, 3).v()[0xf], 1);
+    }
+
+    #[test]
+    fn shift_vx() {
+        let program = [
+            0x60, 0x03, // V0 = 0x03
+            0x61, 0x10, // V1 = 0x10
+            0x80, 0x16, // V0 = V1 >> 1, or V0 >> 1
+            0x62, 0x81, // V2 = 0x81
+            0x63, 0x40, // V3 = 0x40
+            0x82, 0x3e, // V2 = V3 << 1, or V2 << 1
+        ];
+        let vip = execute(&program, Quirks::COSMAC_VIP, 3);
+        assert_eq!((vip.v()[0], vip.v()[0xf]), (0x08, 0));
+        let vip = execute(&program, Quirks::COSMAC_VIP, 6);
+        assert_eq!((vip.v()[2], vip.v()[0xf]), (0x80, 0));
+
+        let schip = execute(&program, Quirks::SCHIP, 3);
+        assert_eq!((schip.v()[0], schip.v()[0xf]), (0x01, 1));
+        let schip = execute(&program, Quirks::SCHIP, 6);
+        assert_eq!((schip.v()[2], schip.v()[0xf]), (0x02, 1));
+    }
+
+    #[test]
+    fn index_increment() {
+        let program = [
+            0x60, 0x01, // V0 = 1
+            0x61, 0x02, // V1 = 2
+            0x62, 0x03, // V2 = 3
+            0xa3, 0x00, // I = 0x300
+            0xf2, 0x55, // save V0..V2
+        ];
+        for (quirks, i) in
+            [(Quirks::COSMAC_VIP, 0x303), (Quirks::CHIP48, 0x302), (Quirks::SCHIP, 0x300)]
+        {
+            let chip8 = execute(&program, quirks, 5);
+            assert_eq!(chip8.i(), i, "{quirks:?}");
+            assert_eq!(chip8.memory()[0x300..0x303], [1, 2, 3]);
         }
-        ProgramCounterState::Next
+    }
+
+    #[test]
+    fn jump_vx() {
+        let program = [
+            0x60, 0x10, // V0 = 0x10
+            0x61, 0x20, // V1 = 0x20
+            0xb1, 0x00, // jump to 0x100 + V0, or 0x100 + V1
+        ];
+        assert_eq!(execute(&program, Quirks::COSMAC_VIP, 3).pc(), 0x110);
+        assert_eq!(execute(&program, Quirks::SCHIP, 3).pc(), 0x120);
+    }
+
+    #[test]
+    fn clip_sprites() {
+        let program = [
+            0x60, 0x3e, // V0 = 62
+            0x61, 0x1e, // V1 = 30
+            0xa0, 0x00, // I = font 0
+            0xd0, 0x15, // draw 5 rows at V0, V1
+        ];
+        let clipped = execute(&program, Quirks::COSMAC_VIP, 4);
+        assert_eq!(lit(&clipped), [(62, 30), (63, 30), (62, 31)]);
+
+        // Bottom rows of the 0 continue at the top, its right half on the left
+        let wrapped = execute(&program, Quirks::XO_CHIP, 4);
+        assert_eq!(
+            lit(&wrapped),
+            [
+                (1, 0),
+                (62, 0),
+                (1, 1),
+                (62, 1),
+                (0, 2),
+                (1, 2),
+                (62, 2),
+                (63, 2),
+                (0, 30),
+                (1, 30),
+                (62, 30),
+                (63, 30),
+                (1, 31),
+                (62, 31),
+            ]
+        );
+    }
+
+    #[test]
+    fn collisions() {
+        let program = [
+            0xa0, 0x00, // I = font 0
+            0xd0, 0x05, // draw at 0, 0
+            0xd0, 0x05, // draw again, erasing it
+        ];
+        let chip8 = execute(&program, Quirks::SCHIP, 2);
+        assert_eq!((lit(&chip8).len(), chip8.v()[0xf]), (14, 0));
+        let chip8 = execute(&program, Quirks::SCHIP, 3);
+        assert_eq!((lit(&chip8).len(), chip8.v()[0xf]), (0, 1));
+    }
+
+    #[test]
+    fn display_wait() {
+        let program = [
+            0xd0, 0x05, // draw at 0, 0
+            0x70, 0x01, // V0 += 1
+        ];
+        let mut chip8 = execute(&program, Quirks::COSMAC_VIP, 2);
+        assert!(chip8.waiting_for_vblank());
+        assert_eq!((chip8.pc(), chip8.v()[0]), (0x202, 0));
+        chip8.tick_timers();
+        chip8.step().unwrap();
+        assert_eq!(chip8.v()[0], 1);
+
+        let chip8 = execute(&program, Quirks::SCHIP, 2);
+        assert!(!chip8.waiting_for_vblank());
+        assert_eq!(chip8.v()[0], 1);
+    }
+
+    #[test]
+    fn schip_hires() {
+        let mut program = vec![
+            0x00, 0xff, // hires
+            0xa2, 0x08, // I = sprite
+            0xd0, 0x00, // draw 16x16 at 0, 0
+            0x00, 0xfe, // lores
+        ];
+        program.extend([0xff; 32]);
+
+        let chip8 = execute(&program, Quirks::SCHIP, 3);
+        assert_eq!((chip8.width(), chip8.height()), (128, 64));
+        let pixels = lit(&chip8);
+        assert_eq!(pixels.len(), 256);
+        assert!(pixels.iter().all(|&(x, y)| x < 16 && y < 16));
+
+        let chip8 = execute(&program, Quirks::SCHIP, 4);
+        assert_eq!((chip8.width(), chip8.height()), (64, 32));
+        assert!(lit(&chip8).is_empty(), "switching resolution clears the screen");
+    }
+
+    #[test]
+    fn schip_big_font() {
+        let program = [
+            0x60, 0x08, // V0 = 8
+            0xf0, 0x30, // I = big font 8
+        ];
+        let chip8 = execute(&program, Quirks::SCHIP, 2);
+        let i = chip8.i();
+        assert_eq!(chip8.memory()[i..i + 10], CHIP8_BIG_FONTSET[80..90]);
+    }
+
+    #[test]
+    fn schip_scroll() {
+        let program = [
+            0x60, 0x08, // V0 = 8
+            0xa2, 0x0c, // I = pixel
+            0xd0, 0x01, // draw at 8, 8
+            0x00, 0xc2, // scroll down 2
+            0x00, 0xfb, // scroll right 4
+            0x00, 0xfc, // scroll left 4
+            0x80, // pixel
+        ];
+        assert_eq!(lit(&execute(&program, Quirks::SCHIP, 3)), [(8, 8)]);
+        assert_eq!(lit(&execute(&program, Quirks::SCHIP, 4)), [(8, 10)]);
+        assert_eq!(lit(&execute(&program, Quirks::SCHIP, 5)), [(12, 10)]);
+        assert_eq!(lit(&execute(&program, Quirks::SCHIP, 6)), [(8, 10)]);
+    }
+
+    #[test]
+    fn xochip_long_index() {
+        let program = [
+            0xf0, 0x00, 0x12, 0x34, // I = 0x1234
+            0x30, 0x00, // skip if V0 == 0
+            0xf0, 0x00, 0x00, 0x00, // I = 0, skipped as a whole
+            0x61, 0x01, // V1 = 1
+        ];
+        let chip8 = execute(&program, Quirks::XO_CHIP, 1);
+        assert_eq!((chip8.i(), chip8.pc()), (0x1234, 0x204));
+        let chip8 = execute(&program, Quirks::XO_CHIP, 3);
+        assert_eq!((chip8.i(), chip8.v()[1]), (0x1234, 1));
+    }
+
+    #[test]
+    fn xochip_planes() {
+        let program = [
+            0xf2, 0x01, // plane 2
+            0xa2, 0x0e, // I = sprites
+            0xd0, 0x11, // draw 1 row at 0, 0
+            0xf3, 0x01, // planes 1 and 2
+            0x60, 0x02, // V0 = 2
+            0xd0, 0x11, // draw a row in each plane at 2, 0
+            0x00, 0xfd, // exit
+            0xf0, 0xf0, // sprites
+        ];
+        let chip8 = execute(&program, Quirks::XO_CHIP, 6);
+        assert_eq!(&chip8.screen_text()[..8], "++##@@..");
+        assert_eq!(chip8.v()[0xf], 1);
+    }
+
+    #[test]
+    fn xochip_register_ranges() {
+        let program = [
+            0x60, 0x01, // V0 = 1
+            0x61, 0x02, // V1 = 2
+            0x62, 0x03, // V2 = 3
+            0xa3, 0x00, // I = 0x300
+            0x50, 0x22, // save V0..V2
+            0xa3, 0x10, // I = 0x310
+            0x52, 0x02, // save V2..V0
+            0xa3, 0x00, // I = 0x300
+            0x53, 0x53, // load V3..V5
+        ];
+        let chip8 = execute(&program, Quirks::XO_CHIP, 9);
+        assert_eq!(chip8.memory()[0x300..0x303], [1, 2, 3]);
+        assert_eq!(chip8.memory()[0x310..0x313], [3, 2, 1]);
+        assert_eq!(chip8.v()[3..6], [1, 2, 3]);
+        assert_eq!(chip8.i(), 0x300, "I is left alone");
+    }
+
+    #[test]
+    fn xochip_audio() {
+        let mut program = vec![
+            0x61, 0x70, // V1 = 112
+            0xf1, 0x3a, // pitch = V1
+            0xa2, 0x08, // I = pattern
+            0xf0, 0x02, // load the audio pattern
+        ];
+        let pattern: Vec<u8> = (0..16).collect();
+        program.extend(&pattern);
+
+        let chip8 = execute(&program, Quirks::XO_CHIP, 4);
+        assert_eq!(chip8.audio_pattern()[..], pattern);
+        assert_eq!(chip8.playback_rate(), 8000.0);
     }
 }
@@ -0,0 +1,164 @@
+use super::Chip8;
+use crate::{
+    breakpoint::{Break, Breakpoint, Register},
+    disasm::Instruction,
+    error::Chip8Error,
+    trace::{TraceEntry, Tracer},
+};
+
+impl Chip8 {
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes `breakpoint`, returning whether it was set
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Records executed instructions with `tracer`, or stops tracing if
+    /// `None`, returning the previous tracer
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Records a data access by the instruction being executed
+    pub(super) fn watch(&mut self, addr: usize, write: bool) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+
+        let pc = self.pc;
+        self.watch_hit = self.breakpoints.iter().find_map(|breakpoint| match breakpoint {
+            Breakpoint::Read(range) if !write && range.contains(&addr) => {
+                Some(Break::Read { pc, addr })
+            }
+            Breakpoint::Write(range) if write && range.contains(&addr) => {
+                Some(Break::Write { pc, addr })
+            }
+            _ => None,
+        });
+    }
+
+    /// Checks the breakpoints on the first instruction before it executes,
+    /// as [`Chip8::check_breakpoints`] only runs after an instruction
+    pub(super) fn check_entry(&self) -> Option<Break> {
+        let op = self.peek_op();
+        self.breakpoints.iter().find_map(|breakpoint| match *breakpoint {
+            Breakpoint::Pc(addr) if addr == self.pc => Some(Break::Pc(addr)),
+            Breakpoint::Opcode { mask, value } => op
+                .filter(|op| op & mask == value)
+                .map(|op| Break::Opcode { pc: self.pc, op }),
+            _ => None,
+        })
+    }
+
+    /// Checks the breakpoints once the instruction at `pc` has executed,
+    /// given the registers from before it ran
+    pub(super) fn check_breakpoints(&mut self, pc: usize, v: [u8; 16], i: usize) -> Option<Break> {
+        if let Some(hit) = self.watch_hit.take() {
+            return Some(hit);
+        }
+
+        let op = self.peek_op();
+        self.breakpoints.iter().find_map(|breakpoint| match *breakpoint {
+            Breakpoint::Register(register) => {
+                let (old, new) = match register {
+                    Register::V(x) => {
+                        let x = (x & 0xf) as usize;
+                        (v[x] as usize, self.v[x] as usize)
+                    }
+                    Register::I => (i, self.i),
+                };
+                (old != new).then_some(Break::Register { pc, register, old, new })
+            }
+            Breakpoint::Pc(addr) if addr == self.pc => Some(Break::Pc(addr)),
+            Breakpoint::Opcode { mask, value } => op
+                .filter(|op| op & mask == value)
+                .map(|op| Break::Opcode { pc: self.pc, op }),
+            _ => None,
+        })
+    }
+
+    /// Traces the instruction at `pc` once it has executed, given the
+    /// registers from before it ran, and dumps the ring buffer on `error`
+    pub(super) fn trace(
+        &mut self,
+        pc: usize,
+        op: u16,
+        v: [u8; 16],
+        i: usize,
+        error: Option<&Chip8Error>,
+    ) {
+        let Some(tracer) = &mut self.tracer else {
+            return;
+        };
+        let writes = std::mem::take(&mut tracer.writes);
+
+        let filter = tracer.filter();
+        let recorded = match Instruction::decode(op) {
+            Some(instruction) => filter.matches(pc, &instruction),
+            // Invalid opcodes have no class, but are kept so that a dump
+            // ends with the instruction that faulted
+            None => filter.range.as_ref().is_none_or(|range| range.contains(&pc)),
+        };
+        if recorded {
+            let mut changes: Vec<_> = (0..16u8)
+                .filter(|&x| v[x as usize] != self.v[x as usize])
+                .map(|x| (Register::V(x), v[x as usize] as usize, self.v[x as usize] as usize))
+                .collect();
+            if i != self.i {
+                changes.push((Register::I, i, self.i));
+            }
+            let byte = |addr: usize| self.mem.get(addr).copied().unwrap_or(0) as u16;
+            let long = byte(pc + 2) << 8 | byte(pc + 3);
+            tracer.record(TraceEntry { pc, op, long, changes, writes });
+        }
+
+        if let Some(error) = error {
+            tracer.dump(&error.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    #[test]
+    fn breakpoint_at_entry() {
+        // V0 := 1, then jump back to the start
+        let mut chip8 = Chip8::new(vec![0x60, 0x01, 0x12, 0x00], Quirks::default()).unwrap();
+        chip8.add_breakpoint(Breakpoint::Pc(0x200));
+
+        assert_eq!(chip8.step(), Ok(Some(Break::Pc(0x200))));
+        assert_eq!((chip8.pc(), chip8.v()[0]), (0x200, 0), "stopped before executing");
+        assert_eq!(chip8.step(), Ok(None));
+        assert_eq!(chip8.step(), Ok(Some(Break::Pc(0x200))));
+        assert_eq!(chip8.v()[0], 1);
+    }
+
+    #[test]
+    fn opcode_breakpoint_at_entry() {
+        let mut chip8 = Chip8::new(vec![0x60, 0x01], Quirks::default()).unwrap();
+        chip8.add_breakpoint(Breakpoint::Opcode { mask: 0xf000, value: 0x6000 });
+        assert_eq!(chip8.step(), Ok(Some(Break::Opcode { pc: 0x200, op: 0x6001 })));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{error::StateError, rng::Rng};
+
+use super::{CHIP8_HIRES_HEIGHT, CHIP8_HIRES_WIDTH, CHIP8_MEM, Chip8};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+/// Version 2 added the random number generator
+const VERSION: u16 = 2;
+const NO_KEY: u8 = 0xff;
+const RNG_SEEDED: u8 = 0;
+const RNG_SCRIPTED: u8 = 1;
+
+impl Chip8 {
+    /// Serializes the complete machine state.
+    ///
+    /// The quirk profile is not part of the state, it stays whatever the
+    /// machine restoring it was created with.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(CHIP8_MEM + CHIP8_HIRES_WIDTH * CHIP8_HIRES_HEIGHT + 256);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+
+        out.extend_from_slice(&self.v);
+        put_u32(&mut out, self.i);
+        put_u32(&mut out, self.pc);
+        out.push(self.sp as u8);
+        for addr in self.stack {
+            put_u32(&mut out, addr);
+        }
+        out.extend_from_slice(&self.mem);
+        for row in &self.screen {
+            out.extend_from_slice(row);
+        }
+        out.push(self.hires as u8);
+        out.push(self.planes);
+        out.extend_from_slice(&self.rpl);
+        out.push(self.exited as u8);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(self.pitch);
+        out.push(self.delay);
+        out.push(self.sound);
+        for key in self.keypad {
+            out.push(key as u8);
+        }
+        out.push(self.waiting_key as u8);
+        out.push(self.last_key.unwrap_or(NO_KEY));
+        out.push(self.vblank_wait as u8);
+        match &self.rng {
+            Rng::Seeded(state) => {
+                out.push(RNG_SEEDED);
+                out.extend_from_slice(&state.to_le_bytes());
+            }
+            Rng::Scripted { bytes, pos } => {
+                out.push(RNG_SCRIPTED);
+                put_u32(&mut out, bytes.len());
+                out.extend_from_slice(bytes);
+                put_u32(&mut out, *pos);
+            }
+        }
+
+        out
+    }
+
+    /// Restores a state produced by [`Chip8::save_state`].
+    ///
+    /// The machine is left untouched if the state cannot be decoded. States
+    /// from before the generator was saved keep the current one.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader { data };
+
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes(r.array()?);
+        if !(1..=VERSION).contains(&version) {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let mut state = Chip8 { quirks: self.quirks, rng: self.rng.clone(), ..Chip8::blank() };
+        state.v = r.array()?;
+        state.i = r.u32()?;
+        state.pc = r.u32()?;
+        state.sp = r.u8()? as usize;
+        for addr in state.stack.iter_mut() {
+            *addr = r.u32()?;
+        }
+        state.mem.copy_from_slice(r.bytes(CHIP8_MEM)?);
+        for row in state.screen.iter_mut() {
+            *row = r.array()?;
+        }
+        state.hires = r.bool()?;
+        state.planes = r.u8()?;
+        state.rpl = r.array()?;
+        state.exited = r.bool()?;
+        state.audio_pattern = r.array()?;
+        state.pitch = r.u8()?;
+        state.delay = r.u8()?;
+        state.sound = r.u8()?;
+        for key in state.keypad.iter_mut() {
+            *key = r.bool()?;
+        }
+        state.waiting_key = r.bool()?;
+        state.last_key = Some(r.u8()?).filter(|&key| key != NO_KEY);
+        state.vblank_wait = r.bool()?;
+        if version >= 2 {
+            state.rng = match r.u8()? {
+                RNG_SEEDED => Rng::Seeded(u64::from_le_bytes(r.array()?)),
+                RNG_SCRIPTED => {
+                    let len = r.u32()?;
+                    let bytes = r.bytes(len)?.to_vec();
+                    let pos = r.u32()?;
+                    if pos >= len.max(1) {
+                        return Err(StateError::Invalid);
+                    }
+                    Rng::Scripted { bytes, pos }
+                }
+                _ => return Err(StateError::Invalid),
+            };
+        }
+
+        if !r.data.is_empty() {
+            return Err(StateError::TrailingData);
+        }
+        if state.sp > state.stack.len() || state.pc >= CHIP8_MEM || state.planes > 0b11 {
+            return Err(StateError::Invalid);
+        }
+
+        state.draw_flag = true;
+        // The breakpoints stopped in front of this instruction already, if
+        // it was saved at one
+        state.entry_checked = true;
+        state.breakpoints = std::mem::take(&mut self.breakpoints);
+        state.tracer = self.tracer.take();
+        *self = state;
+        Ok(())
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, val: usize) {
+    out.extend_from_slice(&(val as u32).to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u32(&mut self) -> Result<usize, StateError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    /// A machine that drew, called a subroutine and used the generator
+    fn machine() -> Chip8 {
+        // V0 := 5, I := 0x20c, draw, V1 := random 0xff, call 0x20e,
+        // then the sprite, then 0x20e: V2 := 1, loop
+        let rom = vec![
+            0x60, 0x05, 0xa2, 0x0c, 0xd0, 0x01, 0xc1, 0xff, 0x22, 0x0e, 0x00, 0x00, 0xf0, 0x00,
+            0x62, 0x01, 0x12, 0x10,
+        ];
+        let mut chip8 = Chip8::new(rom, Quirks::default()).unwrap();
+        chip8.set_rng(Rng::scripted(vec![0x42, 0x17]));
+        chip8.press_key(0xa);
+        for _ in 0..6 {
+            chip8.step().unwrap();
+            chip8.tick_timers();
+        }
+        chip8
+    }
+
+    #[test]
+    fn round_trip() {
+        let chip8 = machine();
+        let state = chip8.save_state();
+
+        let mut restored = Chip8::new(vec![], Quirks::default()).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!((restored.pc(), restored.sp(), restored.i()), (0x210, 1, 0x20c));
+        assert_eq!(restored.v()[..3], [5, 0x42, 1]);
+        assert_eq!(restored.framebuffer(), chip8.framebuffer());
+        assert_eq!(restored.rng(), &Rng::Scripted { bytes: vec![0x42, 0x17], pos: 1 });
+        assert!(restored.keypad()[0xa]);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let state = machine().save_state();
+        let mut chip8 = Chip8::new(vec![0x12, 0x00], Quirks::default()).unwrap();
+        let before = chip8.save_state();
+
+        let mut bad_magic = state.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(chip8.load_state(&bad_magic), Err(StateError::BadMagic));
+
+        for version in [0, VERSION + 1] {
+            let mut unknown = state.clone();
+            unknown[4..6].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(chip8.load_state(&unknown), Err(StateError::UnsupportedVersion(version)));
+        }
+
+        let mut trailing = state.clone();
+        trailing.push(0);
+        assert_eq!(chip8.load_state(&trailing), Err(StateError::TrailingData));
+
+        // The PC is stored after the 16 registers and I
+        let mut invalid = state.clone();
+        invalid[26..30].copy_from_slice(&(CHIP8_MEM as u32).to_le_bytes());
+        assert_eq!(chip8.load_state(&invalid), Err(StateError::Invalid));
+
+        assert_eq!(chip8.save_state(), before, "failed loads must not change the machine");
+    }
+
+    #[test]
+    fn rejects_truncated_states() {
+        let state = machine().save_state();
+        let mut chip8 = Chip8::new(vec![], Quirks::default()).unwrap();
+        for len in 0..state.len() {
+            assert_eq!(chip8.load_state(&state[..len]), Err(StateError::Truncated), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn loads_version_1() {
+        let chip8 = machine();
+        let state = chip8.save_state();
+        // Version 1 ended before the generator: tag, length, 2 bytes, position
+        let mut old = state[..state.len() - (1 + 4 + 2 + 4)].to_vec();
+        old[4..6].copy_from_slice(&1u16.to_le_bytes());
+
+        let mut restored = Chip8::new(vec![], Quirks::default()).unwrap();
+        restored.set_rng(Rng::seeded(7));
+        restored.load_state(&old).unwrap();
+        assert_eq!(restored.rng(), &Rng::seeded(7), "version 1 keeps the current generator");
+        assert_eq!((restored.pc(), restored.v()[1]), (0x210, 0x42));
+        assert_eq!(restored.framebuffer(), chip8.framebuffer());
+    }
+}
@@ -0,0 +1,539 @@
+//! Debugger panel drawn next to the game, showing the machine state and
+//! either the code around the program counter or a memory editor
+
+use std::collections::HashMap;
+
+use chip8em::{CHIP8_BIG_FONTSET, CHIP8_FONTSET, Chip8, Instruction, Symbols, Syntax};
+use winit::keyboard::KeyCode;
+
+use crate::{
+    rect,
+    renderer::{Rect, Vertex},
+};
+
+/// Characters per line, not counting the margins
+const COLUMNS: usize = 32;
+const ROWS: usize = 40;
+/// Instructions shown before the one at PC
+const DISASM_BEFORE: usize = 8;
+/// Bytes per line of the memory view
+const MEMORY_COLUMNS: usize = 8;
+/// Text rows taken by the sprite preview, which fits 16 sprite rows
+const SPRITE_ROWS: usize = 8;
+/// Instructions searched from PC for the `DXYN` giving the sprite height
+const SPRITE_LOOKAHEAD: usize = 16;
+/// Frames a written byte stays highlighted
+const RECENT_WRITE_FRAMES: u8 = 60;
+const FONT_END: usize = CHIP8_FONTSET.len() + CHIP8_BIG_FONTSET.len();
+const ROM_START: usize = 0x200;
+
+const BACKGROUND: [f32; 3] = [0.06, 0.06, 0.06];
+const TEXT: [f32; 3] = [0.6, 0.6, 0.6];
+const DIM: [f32; 3] = [0.25, 0.25, 0.25];
+const HIGHLIGHT: [f32; 3] = [0.9, 0.8, 0.2];
+const INDEX: [f32; 3] = [0.3, 0.8, 0.9];
+const WRITTEN: [f32; 3] = [0.9, 0.35, 0.3];
+const FONT: [f32; 3] = [0.45, 0.45, 0.9];
+const ROM: [f32; 3] = [0.35, 0.7, 0.35];
+const CURSOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Runs of differently colored text
+type Line = Vec<(String, [f32; 3])>;
+
+/// Width of the panel in clip space for a window of the given size, keeping
+/// the glyphs' aspect ratio but never taking more than half the window
+pub fn panel_width(window_width: u32, window_height: u32) -> f32 {
+    if window_width == 0 {
+        return 0.0;
+    }
+    let cell_height = window_height as f32 / ROWS as f32;
+    let cell_width = cell_height * 4.0 / 6.0;
+    let width = cell_width * (COLUMNS + 2) as f32;
+    (2.0 * width / window_width as f32).min(1.0)
+}
+
+/// What the lower part of the panel shows
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Code,
+    Memory,
+}
+
+/// State of the panel between frames
+pub struct Panel {
+    page: Page,
+    /// Selected byte of the memory view, or `None` to follow I
+    cursor: Option<usize>,
+    /// High nibble typed so far while editing, `Some(None)` before any
+    editing: Option<Option<u8>>,
+    rom_size: usize,
+    /// Memory as of the last call to `track_writes`
+    previous: Vec<u8>,
+    /// Frames left to highlight recently written bytes
+    written: HashMap<usize, u8>,
+}
+
+impl Panel {
+    pub fn new(chip8: &Chip8, rom_size: usize) -> Self {
+        Self {
+            page: Page::Code,
+            cursor: None,
+            editing: None,
+            rom_size,
+            previous: chip8.memory().to_vec(),
+            written: HashMap::new(),
+        }
+    }
+
+    /// Records the bytes that changed since the last call, to be called
+    /// after each frame or step
+    pub fn track_writes(&mut self, chip8: &Chip8) {
+        self.written.retain(|_, frames| {
+            *frames -= 1;
+            *frames > 0
+        });
+        for (addr, (old, new)) in self.previous.iter_mut().zip(chip8.memory()).enumerate() {
+            if old != new {
+                *old = *new;
+                self.written.insert(addr, RECENT_WRITE_FRAMES);
+            }
+        }
+    }
+
+    /// Forgets changes made while writes were not being tracked
+    pub fn reset_writes(&mut self, chip8: &Chip8) {
+        self.previous.copy_from_slice(chip8.memory());
+        self.written.clear();
+    }
+
+    /// Handles panel keys, returning whether the key was consumed. Memory
+    /// can only be edited while `paused`.
+    pub fn handle_key(
+        &mut self,
+        code: KeyCode,
+        pressed: bool,
+        chip8: &mut Chip8,
+        paused: bool,
+    ) -> bool {
+        if !paused {
+            self.editing = None;
+        }
+        if !pressed {
+            // Digits typed into memory must not reach the keypad either
+            return self.editing.is_some() && hex_digit(code).is_some();
+        }
+
+        if code == KeyCode::F11 {
+            self.page = if self.page == Page::Code { Page::Memory } else { Page::Code };
+            self.editing = None;
+            return true;
+        }
+        if self.page != Page::Memory {
+            return false;
+        }
+
+        let len = chip8.memory().len();
+        let cursor = self.cursor.unwrap_or(chip8.i()).min(len - 1);
+        let step = |delta: isize| Some(cursor.saturating_add_signed(delta).min(len - 1));
+        let page = (MEMORY_COLUMNS * 16) as isize;
+
+        match code {
+            KeyCode::ArrowLeft => self.cursor = step(-1),
+            KeyCode::ArrowRight => self.cursor = step(1),
+            KeyCode::ArrowUp => self.cursor = step(-(MEMORY_COLUMNS as isize)),
+            KeyCode::ArrowDown => self.cursor = step(MEMORY_COLUMNS as isize),
+            KeyCode::PageUp => self.cursor = step(-page),
+            KeyCode::PageDown => self.cursor = step(page),
+            KeyCode::Home => self.cursor = Some(chip8.pc()),
+            KeyCode::End => self.cursor = Some(chip8.i()),
+            KeyCode::Enter if paused => {
+                self.cursor = Some(cursor);
+                self.editing = if self.editing.is_some() { None } else { Some(None) };
+            }
+            KeyCode::Escape if self.editing.is_some() => self.editing = None,
+            KeyCode::Escape => self.cursor = None,
+            _ => {
+                let (Some(typed), Some(digit)) = (self.editing, hex_digit(code)) else {
+                    return false;
+                };
+                match typed {
+                    Some(high) => {
+                        chip8.memory_mut()[cursor] = high << 4 | digit;
+                        // Edits are not writes by the ROM
+                        self.previous[cursor] = high << 4 | digit;
+                        self.cursor = step(1);
+                        self.editing = Some(None);
+                    }
+                    None => self.editing = Some(Some(digit)),
+                }
+            }
+        }
+        true
+    }
+
+    /// Appends the panel spanning from `left` to the right edge of the window,
+    /// with the framebuffer pixel under the mouse, if any
+    pub fn draw(
+        &self,
+        quads: &mut Vec<Rect>,
+        chip8: &Chip8,
+        symbols: &Symbols,
+        hover: Option<(usize, usize)>,
+        left: f32,
+    ) {
+        quads.push(rect!(left, 1.0, 1.0 - left, 2.0, BACKGROUND));
+
+        // Glyphs are 3x5 pixels in a 4x6 cell, with a one cell margin on either side
+        let cell_width = (1.0 - left) / (COLUMNS + 2) as f32;
+        let cell_height = 2.0 / ROWS as f32;
+
+        let mut lines = lines(chip8, hover);
+        let rows = ROWS.saturating_sub(lines.len());
+        let mut sprite = None;
+        match self.page {
+            Page::Code => lines.extend(disassembly(chip8, symbols, rows)),
+            Page::Memory => {
+                let hex_rows = rows.saturating_sub(SPRITE_ROWS + 3);
+                lines.extend(self.memory(chip8, hex_rows));
+                lines.push(vec![]);
+
+                let (width, bytes) = sprite_at_i(chip8);
+                let height = bytes.len() / (width / 8);
+                lines.push(vec![(format!("SPRITE AT I, {width}x{height}"), TEXT)]);
+                sprite = Some((lines.len(), width, bytes));
+            }
+        }
+
+        for (row, line) in lines.iter().enumerate().take(ROWS) {
+            let y = 1.0 - (row as f32 + 0.5) * cell_height;
+            let mut column = 0;
+            for (text, color) in line {
+                for c in text.chars() {
+                    if column >= COLUMNS {
+                        break;
+                    }
+                    let x = left + (column + 1) as f32 * cell_width;
+                    draw_glyph(quads, c, x, y, cell_width, cell_height, *color);
+                    column += 1;
+                }
+            }
+        }
+
+        if let Some((row, width, bytes)) = sprite {
+            // Square sprite pixels, half a cell high
+            let pixel_height = cell_height / 2.0;
+            let pixel_width = cell_width * 0.75;
+            let x = left + cell_width;
+            let y = 1.0 - (row as f32 + 0.5) * cell_height;
+            let height = bytes.len() / (width / 8);
+            quads.push(rect!(x, y, width as f32 * pixel_width, height as f32 * pixel_height, DIM));
+
+            for (j, row) in bytes.chunks(width / 8).enumerate() {
+                for i in 0..width {
+                    if row[i / 8] & (0x80 >> (i % 8)) != 0 {
+                        let x = x + i as f32 * pixel_width;
+                        let y = y - j as f32 * pixel_height;
+                        quads.push(rect!(x, y, pixel_width, pixel_height, TEXT));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hex dump around the cursor, with the regions and the bytes at PC,
+    /// I and recently written marked
+    fn memory(&self, chip8: &Chip8, rows: usize) -> Vec<Line> {
+        let memory = chip8.memory();
+        let cursor = self.cursor.unwrap_or(chip8.i()).min(memory.len() - 1);
+        let op = memory.get(chip8.pc()..chip8.pc() + 2);
+        let op = op.map(|op| (op[0] as u16) << 8 | op[1] as u16);
+        let size = op.and_then(Instruction::decode).map_or(2, |instruction| instruction.size());
+        let pc = chip8.pc()..chip8.pc() + size;
+        let rom = ROM_START..ROM_START + self.rom_size;
+
+        let mut lines = vec![vec![
+            ("MEM ".to_string(), TEXT),
+            ("FONT ".to_string(), FONT),
+            ("ROM ".to_string(), ROM),
+            ("PC ".to_string(), HIGHLIGHT),
+            ("I ".to_string(), INDEX),
+            ("WRITE".to_string(), WRITTEN),
+        ]];
+
+        // Keep the cursor on the third row where possible
+        let rows = rows.saturating_sub(1).max(1);
+        let last = (memory.len() / MEMORY_COLUMNS).saturating_sub(rows);
+        let first = (cursor / MEMORY_COLUMNS).saturating_sub(2).min(last);
+
+        for row in first..(first + rows).min(memory.len() / MEMORY_COLUMNS) {
+            let start = row * MEMORY_COLUMNS;
+            let region = if start < FONT_END {
+                FONT
+            } else if rom.contains(&start) {
+                ROM
+            } else {
+                DIM
+            };
+            let mut line = vec![(format!("{start:04X}"), region)];
+
+            for (addr, byte) in memory.iter().enumerate().skip(start).take(MEMORY_COLUMNS) {
+                let separator = match (addr == cursor, addr > start && addr - 1 == cursor) {
+                    (true, _) => '[',
+                    (_, true) => ']',
+                    _ => ' ',
+                };
+                let color = if addr == cursor {
+                    CURSOR
+                } else if pc.contains(&addr) {
+                    HIGHLIGHT
+                } else if addr == chip8.i() {
+                    INDEX
+                } else if self.written.contains_key(&addr) {
+                    WRITTEN
+                } else {
+                    TEXT
+                };
+
+                let text = match self.editing {
+                    Some(Some(high)) if addr == cursor => format!("{high:X}_"),
+                    _ => format!("{byte:02X}"),
+                };
+                line.push((separator.to_string(), CURSOR));
+                line.push((text, color));
+            }
+            if cursor == start + MEMORY_COLUMNS - 1 {
+                line.push(("]".to_string(), CURSOR));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Draws the 3x5 glyph for `c` in the cell whose top-left corner is at
+/// `(x, y)`
+fn draw_glyph(
+    quads: &mut Vec<Rect>,
+    c: char,
+    x: f32,
+    y: f32,
+    cell_width: f32,
+    cell_height: f32,
+    color: [f32; 3],
+) {
+    let pixel_width = cell_width / 4.0;
+    let pixel_height = cell_height / 6.0;
+
+    for (j, bits) in glyph(c).into_iter().enumerate() {
+        let y = y - j as f32 * pixel_height;
+        // Lit pixels of a row merged into runs
+        let mut i = 0;
+        while i < 3 {
+            if bits & (0b100 >> i) == 0 {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < 3 && bits & (0b100 >> i) != 0 {
+                i += 1;
+            }
+            let x = x + start as f32 * pixel_width;
+            let w = (i - start) as f32 * pixel_width;
+            quads.push(rect!(x, y, w, pixel_height, color));
+        }
+    }
+}
+
+/// Width and bytes of the sprite at I, as drawn by the `DXYN` at PC or the
+/// next one after it. `DXY0` draws a 16x16 sprite.
+fn sprite_at_i(chip8: &Chip8) -> (usize, Vec<u8>) {
+    let memory = chip8.memory();
+    let mut addr = chip8.pc();
+    let mut n = None;
+    for _ in 0..SPRITE_LOOKAHEAD {
+        let (Some(&hi), Some(&lo)) = (memory.get(addr), memory.get(addr + 1)) else {
+            break;
+        };
+        match Instruction::decode((hi as u16) << 8 | lo as u16) {
+            Some(Instruction::Draw { n: height, .. }) => {
+                n = Some(height as usize);
+                break;
+            }
+            Some(instruction) => addr += instruction.size(),
+            None => addr += 2,
+        }
+    }
+
+    // Without a DXYN nearby, show a font-sized sprite
+    let (width, height) = match n.unwrap_or(5) {
+        0 => (16, 16),
+        n => (8, n),
+    };
+    let start = chip8.i().min(memory.len());
+    let end = (start + width / 8 * height).min(memory.len());
+    let mut bytes = memory[start..end].to_vec();
+    bytes.resize(width / 8 * height, 0);
+    (width, bytes)
+}
+
+/// Registers, stack, keys and the pixel under the mouse shown above either
+/// page
+fn lines(chip8: &Chip8, hover: Option<(usize, usize)>) -> Vec<Line> {
+    let plain = |text: String| vec![(text, TEXT)];
+    let mut lines = vec![
+        plain(format!("PC {:04X}  I {:04X}  SP {:X}", chip8.pc(), chip8.i(), chip8.sp())),
+        plain(format!("DT {:02X}    ST {:02X}", chip8.delay(), chip8.sound())),
+        vec![],
+    ];
+
+    for row in chip8.v().chunks(4).enumerate().map(|(row, regs)| {
+        regs.iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X} {v:02X}", row * 4 + i))
+            .collect::<Vec<_>>()
+            .join("  ")
+    }) {
+        lines.push(plain(row));
+    }
+    lines.push(vec![]);
+
+    lines.push(plain("STACK".to_string()));
+    for chunk in chip8.stack().chunks(4) {
+        let addrs: Vec<_> = chunk.iter().map(|addr| format!("{addr:04X}")).collect();
+        lines.push(plain(format!(" {}", addrs.join(" "))));
+    }
+    lines.push(vec![]);
+
+    let mut keys = vec![("KEYS ".to_string(), TEXT)];
+    for (key, &held) in chip8.keypad().iter().enumerate() {
+        keys.push((format!("{key:X}"), if held { HIGHLIGHT } else { DIM }));
+    }
+    lines.push(keys);
+
+    // Planes as in the framebuffer, bit 0 for plane 1 and bit 1 for plane 2
+    let pixel = match hover {
+        Some((x, y)) => {
+            format!("PIXEL X {x:02X} Y {y:02X} PLANES {}", chip8.framebuffer()[y][x] & 3)
+        }
+        None => "PIXEL".to_string(),
+    };
+    lines.push(plain(pixel));
+    lines.push(vec![]);
+    lines
+}
+
+/// Instructions around PC, decoded linearly from a little before it, with a
+/// line for each label
+fn disassembly(chip8: &Chip8, symbols: &Symbols, rows: usize) -> Vec<Line> {
+    let memory = chip8.memory();
+    let pc = chip8.pc();
+    let mut addr = pc.saturating_sub(DISASM_BEFORE * 2);
+    let mut lines = vec![];
+
+    while lines.len() < rows && addr < memory.len() {
+        let (text, mut len) = chip8em::disassemble(memory, addr, Syntax::Cowboy, symbols);
+        // Decoding may have started mid-instruction, so resynchronize on PC
+        if addr < pc && addr + len > pc {
+            len = pc - addr;
+        }
+
+        let bytes: String = memory[addr..(addr + len).min(memory.len())]
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        if let Some(name) = symbols.name(addr) {
+            lines.push(vec![(format!("{name}:"), TEXT)]);
+        }
+        let line = format!("{addr:04X}  {bytes:<9}{text}");
+        lines.push(if addr == pc {
+            vec![(format!("> {line}"), HIGHLIGHT)]
+        } else {
+            vec![(format!("  {line}"), TEXT)]
+        });
+        addr += len;
+    }
+    lines
+}
+
+/// 3x5 glyph, one row per byte with the leftmost pixel in bit 2
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 2, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' if c == 'x' => [0, 5, 2, 5, 0],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ' ' => [0, 0, 0, 0, 0],
+        '.' => [0, 0, 0, 0, 2],
+        ',' => [0, 0, 0, 2, 4],
+        ':' => [0, 2, 0, 2, 0],
+        '-' => [0, 0, 7, 0, 0],
+        '+' => [0, 2, 7, 2, 0],
+        '=' => [0, 7, 0, 7, 0],
+        '_' => [0, 0, 0, 0, 7],
+        '/' => [1, 1, 2, 4, 4],
+        '#' => [5, 7, 5, 7, 5],
+        '>' => [4, 2, 1, 2, 4],
+        '[' => [6, 4, 4, 4, 6],
+        ']' => [3, 1, 1, 1, 3],
+        '(' => [1, 2, 2, 2, 1],
+        ')' => [4, 2, 2, 2, 4],
+        _ => [7, 1, 2, 0, 2],
+    }
+}
+
+/// Value of a hex digit key
+fn hex_digit(code: KeyCode) -> Option<u8> {
+    let digit = match code {
+        KeyCode::Digit0 | KeyCode::Numpad0 => 0x0,
+        KeyCode::Digit1 | KeyCode::Numpad1 => 0x1,
+        KeyCode::Digit2 | KeyCode::Numpad2 => 0x2,
+        KeyCode::Digit3 | KeyCode::Numpad3 => 0x3,
+        KeyCode::Digit4 | KeyCode::Numpad4 => 0x4,
+        KeyCode::Digit5 | KeyCode::Numpad5 => 0x5,
+        KeyCode::Digit6 | KeyCode::Numpad6 => 0x6,
+        KeyCode::Digit7 | KeyCode::Numpad7 => 0x7,
+        KeyCode::Digit8 | KeyCode::Numpad8 => 0x8,
+        KeyCode::Digit9 | KeyCode::Numpad9 => 0x9,
+        KeyCode::KeyA => 0xa,
+        KeyCode::KeyB => 0xb,
+        KeyCode::KeyC => 0xc,
+        KeyCode::KeyD => 0xd,
+        KeyCode::KeyE => 0xe,
+        KeyCode::KeyF => 0xf,
+        _ => return None,
+    };
+    Some(digit)
+}
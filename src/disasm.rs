@@ -0,0 +1,769 @@
+use std::collections::BTreeMap;
+
+use crate::symbols::Symbols;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// `00CN` scroll down N rows
+    ScrollDown(u8),
+    /// `00E0`
+    Clear,
+    /// `00EE`
+    Return,
+    /// `00FB` scroll right 4 pixels
+    ScrollRight,
+    /// `00FC` scroll left 4 pixels
+    ScrollLeft,
+    /// `00FD`
+    Exit,
+    /// `00FE`
+    Lores,
+    /// `00FF`
+    Hires,
+    /// `1NNN`
+    Jump(u16),
+    /// `2NNN`
+    Call(u16),
+    /// `3XNN` skip if VX == NN
+    SkipEqImm { x: u8, kk: u8 },
+    /// `4XNN` skip if VX != NN
+    SkipNeImm { x: u8, kk: u8 },
+    /// `5XY0` skip if VX == VY
+    SkipEq { x: u8, y: u8 },
+    /// `5XY2` store VX to VY at I
+    SaveRange { x: u8, y: u8 },
+    /// `5XY3` load VX to VY from I
+    LoadRange { x: u8, y: u8 },
+    /// `6XNN`
+    LoadImm { x: u8, kk: u8 },
+    /// `7XNN`
+    AddImm { x: u8, kk: u8 },
+    /// `8XY0`
+    Move { x: u8, y: u8 },
+    /// `8XY1`
+    Or { x: u8, y: u8 },
+    /// `8XY2`
+    And { x: u8, y: u8 },
+    /// `8XY3`
+    Xor { x: u8, y: u8 },
+    /// `8XY4`
+    Add { x: u8, y: u8 },
+    /// `8XY5` VX -= VY
+    Sub { x: u8, y: u8 },
+    /// `8XY6`
+    Shr { x: u8, y: u8 },
+    /// `8XY7` VX = VY - VX
+    SubN { x: u8, y: u8 },
+    /// `8XYE`
+    Shl { x: u8, y: u8 },
+    /// `9XY0` skip if VX != VY
+    SkipNe { x: u8, y: u8 },
+    /// `ANNN`
+    LoadI(u16),
+    /// `BNNN`, which also names VX for the jump quirk
+    JumpOffset { x: u8, nnn: u16 },
+    /// `CXNN`
+    Random { x: u8, kk: u8 },
+    /// `DXYN`
+    Draw { x: u8, y: u8, n: u8 },
+    /// `EX9E` skip if key VX is held
+    SkipKey(u8),
+    /// `EXA1` skip if key VX is not held
+    SkipNotKey(u8),
+    /// `F000 NNNN`, the address being the following word
+    LoadLongI,
+    /// `FN01`
+    Plane(u8),
+    /// `F002` load the audio pattern from I
+    Audio,
+    /// `FX07`
+    GetDelay(u8),
+    /// `FX0A`
+    WaitKey(u8),
+    /// `FX15`
+    SetDelay(u8),
+    /// `FX18`
+    SetSound(u8),
+    /// `FX1E`
+    AddI(u8),
+    /// `FX29`
+    Font(u8),
+    /// `FX30`
+    BigFont(u8),
+    /// `FX33`
+    Bcd(u8),
+    /// `FX3A`
+    Pitch(u8),
+    /// `FX55`
+    Save(u8),
+    /// `FX65`
+    Load(u8),
+    /// `FX75`
+    SaveFlags(u8),
+    /// `FX85`
+    LoadFlags(u8),
+}
+
+/// Mnemonic style used when rendering instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Cowboy's `LD V0, 0x12` style
+    Cowboy,
+    /// Octo's `v0 := 0x12` style
+    Octo,
+}
+
+impl Syntax {
+    pub const NAMES: [(&'static str, Self); 2] = [("cowboy", Self::Cowboy), ("octo", Self::Octo)];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, syntax)| *syntax)
+    }
+}
+
+/// Kind of instruction, used to filter traces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass {
+    /// Jumps, calls, returns and exit
+    Flow,
+    /// Conditional skips on register values
+    Skip,
+    /// Arithmetic and logic on V registers, including `CXNN`
+    Alu,
+    /// Loads and additions to I, including font lookups
+    Index,
+    /// Register saves and loads, BCD and the flag registers
+    Memory,
+    /// Drawing, clearing, scrolling, resolution and plane selection
+    Display,
+    /// Key skips and waiting for a key
+    Input,
+    /// Delay and sound timers
+    Timer,
+    /// XO-CHIP audio pattern and pitch
+    Audio,
+}
+
+impl InstructionClass {
+    pub const NAMES: [(&'static str, Self); 9] = [
+        ("flow", Self::Flow),
+        ("skip", Self::Skip),
+        ("alu", Self::Alu),
+        ("index", Self::Index),
+        ("memory", Self::Memory),
+        ("display", Self::Display),
+        ("input", Self::Input),
+        ("timer", Self::Timer),
+        ("audio", Self::Audio),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, class)| *class)
+    }
+}
+
+impl Instruction {
+    /// Decodes `op`, or returns `None` if it is not a valid instruction
+    pub fn decode(op: u16) -> Option<Self> {
+        let x = ((op >> 8) & 0xf) as u8;
+        let y = ((op >> 4) & 0xf) as u8;
+        let n = (op & 0xf) as u8;
+        let kk = (op & 0xff) as u8;
+        let nnn = op & 0xfff;
+
+        let instruction = match (op >> 12, x, y, n) {
+            (0x0, 0x0, 0xc, _) => Self::ScrollDown(n),
+            (0x0, 0x0, 0xe, 0x0) => Self::Clear,
+            (0x0, 0x0, 0xe, 0xe) => Self::Return,
+            (0x0, 0x0, 0xf, 0xb) => Self::ScrollRight,
+            (0x0, 0x0, 0xf, 0xc) => Self::ScrollLeft,
+            (0x0, 0x0, 0xf, 0xd) => Self::Exit,
+            (0x0, 0x0, 0xf, 0xe) => Self::Lores,
+            (0x0, 0x0, 0xf, 0xf) => Self::Hires,
+            (0x1, _, _, _) => Self::Jump(nnn),
+            (0x2, _, _, _) => Self::Call(nnn),
+            (0x3, _, _, _) => Self::SkipEqImm { x, kk },
+            (0x4, _, _, _) => Self::SkipNeImm { x, kk },
+            (0x5, _, _, 0x0) => Self::SkipEq { x, y },
+            (0x5, _, _, 0x2) => Self::SaveRange { x, y },
+            (0x5, _, _, 0x3) => Self::LoadRange { x, y },
+            (0x6, _, _, _) => Self::LoadImm { x, kk },
+            (0x7, _, _, _) => Self::AddImm { x, kk },
+            (0x8, _, _, 0x0) => Self::Move { x, y },
+            (0x8, _, _, 0x1) => Self::Or { x, y },
+            (0x8, _, _, 0x2) => Self::And { x, y },
+            (0x8, _, _, 0x3) => Self::Xor { x, y },
+            (0x8, _, _, 0x4) => Self::Add { x, y },
+            (0x8, _, _, 0x5) => Self::Sub { x, y },
+            (0x8, _, _, 0x6) => Self::Shr { x, y },
+            (0x8, _, _, 0x7) => Self::SubN { x, y },
+            (0x8, _, _, 0xe) => Self::Shl { x, y },
+            (0x9, _, _, 0x0) => Self::SkipNe { x, y },
+            (0xa, _, _, _) => Self::LoadI(nnn),
+            (0xb, _, _, _) => Self::JumpOffset { x, nnn },
+            (0xc, _, _, _) => Self::Random { x, kk },
+            (0xd, _, _, _) => Self::Draw { x, y, n },
+            (0xe, _, 0x9, 0xe) => Self::SkipKey(x),
+            (0xe, _, 0xa, 0x1) => Self::SkipNotKey(x),
+            (0xf, 0x0, 0x0, 0x0) => Self::LoadLongI,
+            (0xf, _, 0x0, 0x1) => Self::Plane(x),
+            (0xf, 0x0, 0x0, 0x2) => Self::Audio,
+            (0xf, _, 0x0, 0x7) => Self::GetDelay(x),
+            (0xf, _, 0x0, 0xa) => Self::WaitKey(x),
+            (0xf, _, 0x1, 0x5) => Self::SetDelay(x),
+            (0xf, _, 0x1, 0x8) => Self::SetSound(x),
+            (0xf, _, 0x1, 0xe) => Self::AddI(x),
+            (0xf, _, 0x2, 0x9) => Self::Font(x),
+            (0xf, _, 0x3, 0x0) => Self::BigFont(x),
+            (0xf, _, 0x3, 0x3) => Self::Bcd(x),
+            (0xf, _, 0x3, 0xa) => Self::Pitch(x),
+            (0xf, _, 0x5, 0x5) => Self::Save(x),
+            (0xf, _, 0x6, 0x5) => Self::Load(x),
+            (0xf, _, 0x7, 0x5) => Self::SaveFlags(x),
+            (0xf, _, 0x8, 0x5) => Self::LoadFlags(x),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Encodes the instruction back into its opcode, the inverse of
+    /// [`Instruction::decode`]
+    pub fn encode(&self) -> u16 {
+        let xkk = |high: u16, x: u8, kk: u8| high << 12 | (x as u16) << 8 | kk as u16;
+        let xy = |high: u16, x: u8, y: u8, n: u16| xkk(high, x, y << 4) | n;
+        let fx = |x: u8, low: u16| 0xf000 | (x as u16) << 8 | low;
+
+        match *self {
+            Self::ScrollDown(n) => 0x00c0 | n as u16,
+            Self::Clear => 0x00e0,
+            Self::Return => 0x00ee,
+            Self::ScrollRight => 0x00fb,
+            Self::ScrollLeft => 0x00fc,
+            Self::Exit => 0x00fd,
+            Self::Lores => 0x00fe,
+            Self::Hires => 0x00ff,
+            Self::Jump(nnn) => 0x1000 | nnn,
+            Self::Call(nnn) => 0x2000 | nnn,
+            Self::SkipEqImm { x, kk } => xkk(0x3, x, kk),
+            Self::SkipNeImm { x, kk } => xkk(0x4, x, kk),
+            Self::SkipEq { x, y } => xy(0x5, x, y, 0x0),
+            Self::SaveRange { x, y } => xy(0x5, x, y, 0x2),
+            Self::LoadRange { x, y } => xy(0x5, x, y, 0x3),
+            Self::LoadImm { x, kk } => xkk(0x6, x, kk),
+            Self::AddImm { x, kk } => xkk(0x7, x, kk),
+            Self::Move { x, y } => xy(0x8, x, y, 0x0),
+            Self::Or { x, y } => xy(0x8, x, y, 0x1),
+            Self::And { x, y } => xy(0x8, x, y, 0x2),
+            Self::Xor { x, y } => xy(0x8, x, y, 0x3),
+            Self::Add { x, y } => xy(0x8, x, y, 0x4),
+            Self::Sub { x, y } => xy(0x8, x, y, 0x5),
+            Self::Shr { x, y } => xy(0x8, x, y, 0x6),
+            Self::SubN { x, y } => xy(0x8, x, y, 0x7),
+            Self::Shl { x, y } => xy(0x8, x, y, 0xe),
+            Self::SkipNe { x, y } => xy(0x9, x, y, 0x0),
+            Self::LoadI(nnn) => 0xa000 | nnn,
+            // The X of the jump quirk is part of NNN
+            Self::JumpOffset { nnn, .. } => 0xb000 | nnn,
+            Self::Random { x, kk } => xkk(0xc, x, kk),
+            Self::Draw { x, y, n } => xy(0xd, x, y, n as u16),
+            Self::SkipKey(x) => xkk(0xe, x, 0x9e),
+            Self::SkipNotKey(x) => xkk(0xe, x, 0xa1),
+            Self::LoadLongI => 0xf000,
+            Self::Plane(n) => fx(n, 0x01),
+            Self::Audio => 0xf002,
+            Self::GetDelay(x) => fx(x, 0x07),
+            Self::WaitKey(x) => fx(x, 0x0a),
+            Self::SetDelay(x) => fx(x, 0x15),
+            Self::SetSound(x) => fx(x, 0x18),
+            Self::AddI(x) => fx(x, 0x1e),
+            Self::Font(x) => fx(x, 0x29),
+            Self::BigFont(x) => fx(x, 0x30),
+            Self::Bcd(x) => fx(x, 0x33),
+            Self::Pitch(x) => fx(x, 0x3a),
+            Self::Save(x) => fx(x, 0x55),
+            Self::Load(x) => fx(x, 0x65),
+            Self::SaveFlags(x) => fx(x, 0x75),
+            Self::LoadFlags(x) => fx(x, 0x85),
+        }
+    }
+
+    /// Size in bytes, including the address following `F000`
+    pub fn size(&self) -> usize {
+        match self {
+            Self::LoadLongI => 4,
+            _ => 2,
+        }
+    }
+
+    /// Whether the instruction may skip the one after it
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Self::SkipEqImm { .. }
+                | Self::SkipNeImm { .. }
+                | Self::SkipEq { .. }
+                | Self::SkipNe { .. }
+                | Self::SkipKey(_)
+                | Self::SkipNotKey(_)
+        )
+    }
+
+    pub fn class(&self) -> InstructionClass {
+        use InstructionClass::*;
+
+        match self {
+            Self::Return | Self::Jump(_) | Self::Call(_) | Self::JumpOffset { .. } | Self::Exit => {
+                Flow
+            }
+            Self::SkipEqImm { .. }
+            | Self::SkipNeImm { .. }
+            | Self::SkipEq { .. }
+            | Self::SkipNe { .. } => Skip,
+            Self::LoadImm { .. }
+            | Self::AddImm { .. }
+            | Self::Move { .. }
+            | Self::Or { .. }
+            | Self::And { .. }
+            | Self::Xor { .. }
+            | Self::Add { .. }
+            | Self::Sub { .. }
+            | Self::Shr { .. }
+            | Self::SubN { .. }
+            | Self::Shl { .. }
+            | Self::Random { .. } => Alu,
+            Self::LoadI(_) | Self::LoadLongI | Self::AddI(_) | Self::Font(_) | Self::BigFont(_) => {
+                Index
+            }
+            Self::SaveRange { .. }
+            | Self::LoadRange { .. }
+            | Self::Bcd(_)
+            | Self::Save(_)
+            | Self::Load(_)
+            | Self::SaveFlags(_)
+            | Self::LoadFlags(_) => Memory,
+            Self::ScrollDown(_)
+            | Self::Clear
+            | Self::ScrollRight
+            | Self::ScrollLeft
+            | Self::Lores
+            | Self::Hires
+            | Self::Draw { .. }
+            | Self::Plane(_) => Display,
+            Self::SkipKey(_) | Self::SkipNotKey(_) | Self::WaitKey(_) => Input,
+            Self::GetDelay(_) | Self::SetDelay(_) | Self::SetSound(_) => Timer,
+            Self::Audio | Self::Pitch(_) => Audio,
+        }
+    }
+
+    /// Renders the instruction. `long` is the address following `F000`, and
+    /// `label` names addresses that should not be shown as numbers.
+    pub fn format(
+        &self,
+        syntax: Syntax,
+        long: u16,
+        label: &dyn Fn(u16) -> Option<String>,
+    ) -> String {
+        let addr = |addr: u16| label(addr).unwrap_or_else(|| format!("{addr:#05x}"));
+        let long = label(long).unwrap_or_else(|| format!("{long:#06x}"));
+        match syntax {
+            Syntax::Cowboy => self.cowboy(addr, &long),
+            Syntax::Octo => self.octo(addr, &long),
+        }
+    }
+
+    fn cowboy(&self, addr: impl Fn(u16) -> String, long: &str) -> String {
+        match *self {
+            Self::ScrollDown(n) => format!("SCD {n:#x}"),
+            Self::Clear => "CLS".to_string(),
+            Self::Return => "RET".to_string(),
+            Self::ScrollRight => "SCR".to_string(),
+            Self::ScrollLeft => "SCL".to_string(),
+            Self::Exit => "EXIT".to_string(),
+            Self::Lores => "LOW".to_string(),
+            Self::Hires => "HIGH".to_string(),
+            Self::Jump(nnn) => format!("JP {}", addr(nnn)),
+            Self::Call(nnn) => format!("CALL {}", addr(nnn)),
+            Self::SkipEqImm { x, kk } => format!("SE V{x:X}, {kk:#04x}"),
+            Self::SkipNeImm { x, kk } => format!("SNE V{x:X}, {kk:#04x}"),
+            Self::SkipEq { x, y } => format!("SE V{x:X}, V{y:X}"),
+            Self::SaveRange { x, y } => format!("SAVE V{x:X}-V{y:X}"),
+            Self::LoadRange { x, y } => format!("LOAD V{x:X}-V{y:X}"),
+            Self::LoadImm { x, kk } => format!("LD V{x:X}, {kk:#04x}"),
+            Self::AddImm { x, kk } => format!("ADD V{x:X}, {kk:#04x}"),
+            Self::Move { x, y } => format!("LD V{x:X}, V{y:X}"),
+            Self::Or { x, y } => format!("OR V{x:X}, V{y:X}"),
+            Self::And { x, y } => format!("AND V{x:X}, V{y:X}"),
+            Self::Xor { x, y } => format!("XOR V{x:X}, V{y:X}"),
+            Self::Add { x, y } => format!("ADD V{x:X}, V{y:X}"),
+            Self::Sub { x, y } => format!("SUB V{x:X}, V{y:X}"),
+            Self::Shr { x, y } => format!("SHR V{x:X}, V{y:X}"),
+            Self::SubN { x, y } => format!("SUBN V{x:X}, V{y:X}"),
+            Self::Shl { x, y } => format!("SHL V{x:X}, V{y:X}"),
+            Self::SkipNe { x, y } => format!("SNE V{x:X}, V{y:X}"),
+            Self::LoadI(nnn) => format!("LD I, {}", addr(nnn)),
+            Self::JumpOffset { nnn, .. } => format!("JP V0, {}", addr(nnn)),
+            Self::Random { x, kk } => format!("RND V{x:X}, {kk:#04x}"),
+            Self::Draw { x, y, n } => format!("DRW V{x:X}, V{y:X}, {n:#x}"),
+            Self::SkipKey(x) => format!("SKP V{x:X}"),
+            Self::SkipNotKey(x) => format!("SKNP V{x:X}"),
+            Self::LoadLongI => format!("LD I, {long}"),
+            Self::Plane(n) => format!("PLANE {n:#x}"),
+            Self::Audio => "AUDIO".to_string(),
+            Self::GetDelay(x) => format!("LD V{x:X}, DT"),
+            Self::WaitKey(x) => format!("LD V{x:X}, K"),
+            Self::SetDelay(x) => format!("LD DT, V{x:X}"),
+            Self::SetSound(x) => format!("LD ST, V{x:X}"),
+            Self::AddI(x) => format!("ADD I, V{x:X}"),
+            Self::Font(x) => format!("LD F, V{x:X}"),
+            Self::BigFont(x) => format!("LD HF, V{x:X}"),
+            Self::Bcd(x) => format!("LD B, V{x:X}"),
+            Self::Pitch(x) => format!("PITCH V{x:X}"),
+            Self::Save(x) => format!("LD [I], V{x:X}"),
+            Self::Load(x) => format!("LD V{x:X}, [I]"),
+            Self::SaveFlags(x) => format!("LD R, V{x:X}"),
+            Self::LoadFlags(x) => format!("LD V{x:X}, R"),
+        }
+    }
+
+    fn octo(&self, addr: impl Fn(u16) -> String, long: &str) -> String {
+        match *self {
+            Self::ScrollDown(n) => format!("scroll-down {n}"),
+            Self::Clear => "clear".to_string(),
+            Self::Return => "return".to_string(),
+            Self::ScrollRight => "scroll-right".to_string(),
+            Self::ScrollLeft => "scroll-left".to_string(),
+            Self::Exit => "exit".to_string(),
+            Self::Lores => "lores".to_string(),
+            Self::Hires => "hires".to_string(),
+            Self::Jump(nnn) => format!("jump {}", addr(nnn)),
+            Self::Call(nnn) => format!(":call {}", addr(nnn)),
+            // Octo spells out the condition under which the next
+            // instruction runs, the opposite of the skip condition
+            Self::SkipEqImm { x, kk } => format!("if v{x:x} != {kk:#04x} then"),
+            Self::SkipNeImm { x, kk } => format!("if v{x:x} == {kk:#04x} then"),
+            Self::SkipEq { x, y } => format!("if v{x:x} != v{y:x} then"),
+            Self::SaveRange { x, y } => format!("save v{x:x} - v{y:x}"),
+            Self::LoadRange { x, y } => format!("load v{x:x} - v{y:x}"),
+            Self::LoadImm { x, kk } => format!("v{x:x} := {kk:#04x}"),
+            Self::AddImm { x, kk } => format!("v{x:x} += {kk:#04x}"),
+            Self::Move { x, y } => format!("v{x:x} := v{y:x}"),
+            Self::Or { x, y } => format!("v{x:x} |= v{y:x}"),
+            Self::And { x, y } => format!("v{x:x} &= v{y:x}"),
+            Self::Xor { x, y } => format!("v{x:x} ^= v{y:x}"),
+            Self::Add { x, y } => format!("v{x:x} += v{y:x}"),
+            Self::Sub { x, y } => format!("v{x:x} -= v{y:x}"),
+            Self::Shr { x, y } => format!("v{x:x} >>= v{y:x}"),
+            Self::SubN { x, y } => format!("v{x:x} =- v{y:x}"),
+            Self::Shl { x, y } => format!("v{x:x} <<= v{y:x}"),
+            Self::SkipNe { x, y } => format!("if v{x:x} == v{y:x} then"),
+            Self::LoadI(nnn) => format!("i := {}", addr(nnn)),
+            Self::JumpOffset { nnn, .. } => format!("jump0 {}", addr(nnn)),
+            Self::Random { x, kk } => format!("v{x:x} := random {kk:#04x}"),
+            Self::Draw { x, y, n } => format!("sprite v{x:x} v{y:x} {n}"),
+            Self::SkipKey(x) => format!("if v{x:x} -key then"),
+            Self::SkipNotKey(x) => format!("if v{x:x} key then"),
+            Self::LoadLongI => format!("i := long {long}"),
+            Self::Plane(n) => format!("plane {n}"),
+            Self::Audio => "audio".to_string(),
+            Self::GetDelay(x) => format!("v{x:x} := delay"),
+            Self::WaitKey(x) => format!("v{x:x} := key"),
+            Self::SetDelay(x) => format!("delay := v{x:x}"),
+            Self::SetSound(x) => format!("buzzer := v{x:x}"),
+            Self::AddI(x) => format!("i += v{x:x}"),
+            Self::Font(x) => format!("i := hex v{x:x}"),
+            Self::BigFont(x) => format!("i := bighex v{x:x}"),
+            Self::Bcd(x) => format!("bcd v{x:x}"),
+            Self::Pitch(x) => format!("pitch := v{x:x}"),
+            Self::Save(x) => format!("save v{x:x}"),
+            Self::Load(x) => format!("load v{x:x}"),
+            Self::SaveFlags(x) => format!("saveflags v{x:x}"),
+            Self::LoadFlags(x) => format!("loadflags v{x:x}"),
+        }
+    }
+}
+
+/// Disassembles the instruction at `addr`, returning its mnemonic and length
+/// in bytes. Bytes that do not form a valid instruction are shown as data, and
+/// addresses with a name in `symbols` are shown by name.
+pub fn disassemble(mem: &[u8], addr: usize, syntax: Syntax, symbols: &Symbols) -> (String, usize) {
+    let byte = |addr: usize| mem.get(addr).copied().unwrap_or(0) as u16;
+    let op = byte(addr) << 8 | byte(addr + 1);
+    let long = byte(addr + 2) << 8 | byte(addr + 3);
+
+    match Instruction::decode(op) {
+        Some(instruction) => {
+            let label = |addr: u16| symbols.name(addr as usize).map(str::to_string);
+            let text = instruction.format(syntax, long, &label);
+            (text, instruction.size())
+        }
+        None => (data(syntax, &[(op >> 8) as u8, op as u8]), 2),
+    }
+}
+
+/// Disassembles a whole ROM loaded at 0x200.
+///
+/// Code is told apart from data by following every path of execution from
+/// the entry point, so bytes only reached through computed jumps (`BNNN`)
+/// are listed as data. Jump and call targets and addresses loaded into I
+/// get labels, named after `symbols` where it has a name for them.
+pub fn disassemble_rom(rom: &[u8], syntax: Syntax, symbols: &Symbols) -> String {
+    const START: usize = 0x200;
+
+    let mut mem = vec![0; START + rom.len()];
+    mem[START..].copy_from_slice(rom);
+    let end = mem.len();
+    let byte = |addr: usize| mem.get(addr).copied().unwrap_or(0) as u16;
+    let decode = |addr: usize| {
+        let op = byte(addr) << 8 | byte(addr + 1);
+        Instruction::decode(op).filter(|instruction| addr + instruction.size() <= end)
+    };
+
+    // Follow every reachable path, marking the bytes each instruction covers
+    let mut code: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut covered = vec![false; end];
+    let mut labels: BTreeMap<usize, String> = BTreeMap::new();
+    for (name, addr) in symbols.iter() {
+        labels.insert(addr, symbols.name(addr).unwrap_or(name).to_string());
+    }
+    // A symbol may already use the entry label's name for another address
+    let entry = Some(entry_label(syntax)).filter(|name| symbols.addr(name).is_none());
+    labels.entry(START).or_insert_with(|| entry.unwrap_or_else(|| "entry".to_string()));
+    let mut pending = vec![START];
+
+    while let Some(mut addr) = pending.pop() {
+        while addr >= START && !code.contains_key(&addr) {
+            let Some(instruction) = decode(addr) else {
+                break;
+            };
+            code.insert(addr, instruction);
+            covered[addr..addr + instruction.size()].fill(true);
+            let next = addr + instruction.size();
+
+            match instruction {
+                Instruction::Jump(nnn) => {
+                    labels.entry(nnn as usize).or_insert_with(|| format!("label_{nnn:03x}"));
+                    pending.push(nnn as usize);
+                    break;
+                }
+                Instruction::Call(nnn) => {
+                    labels.entry(nnn as usize).or_insert_with(|| format!("sub_{nnn:03x}"));
+                    pending.push(nnn as usize);
+                }
+                Instruction::LoadI(nnn) => {
+                    labels.entry(nnn as usize).or_insert_with(|| format!("data_{nnn:03x}"));
+                }
+                Instruction::LoadLongI => {
+                    let long = byte(addr + 2) << 8 | byte(addr + 3);
+                    labels.entry(long as usize).or_insert_with(|| format!("data_{long:04x}"));
+                }
+                Instruction::Return | Instruction::Exit | Instruction::JumpOffset { .. } => break,
+                _ => {}
+            }
+
+            // A skip can land on either of the two following instructions
+            if instruction.is_skip() && let Some(skipped) = decode(next) {
+                pending.push(next + skipped.size());
+            }
+            addr = next;
+        }
+    }
+
+    // Labels inside an instruction or outside the ROM cannot be defined, so
+    // those addresses are shown as numbers instead
+    let lines = line_starts(&code, &covered, &labels, START);
+    labels.retain(|addr, _| lines.binary_search(addr).is_ok());
+
+    let name = |addr: u16| labels.get(&(addr as usize)).cloned();
+    let mut out = String::new();
+    for (i, &addr) in lines.iter().enumerate() {
+        if let Some(label) = labels.get(&addr) {
+            out += &match syntax {
+                Syntax::Cowboy => format!("{label}:\n"),
+                Syntax::Octo => format!(": {label}\n"),
+            };
+        }
+
+        let line = match code.get(&addr) {
+            Some(instruction) => {
+                let long = byte(addr + 2) << 8 | byte(addr + 3);
+                instruction.format(syntax, long, &name)
+            }
+            None => data(syntax, &mem[addr..lines.get(i + 1).copied().unwrap_or(end)]),
+        };
+        out += &format!("    {line}\n");
+    }
+    out
+}
+
+/// Addresses at which a listing line starts: each instruction, and runs of
+/// data of up to 8 bytes that are split at labels
+fn line_starts(
+    code: &BTreeMap<usize, Instruction>,
+    covered: &[bool],
+    labels: &BTreeMap<usize, String>,
+    start: usize,
+) -> Vec<usize> {
+    let end = covered.len();
+    let mut lines = vec![];
+    let mut addr = start;
+
+    while addr < end {
+        lines.push(addr);
+        if let Some(instruction) = code.get(&addr) {
+            addr += instruction.size();
+            continue;
+        }
+
+        let mut run = addr + 1;
+        while run < end && run - addr < 8 && !covered[run] && !labels.contains_key(&run) {
+            run += 1;
+        }
+        addr = run;
+    }
+    lines
+}
+
+fn entry_label(syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowboy => "start".to_string(),
+        Syntax::Octo => "main".to_string(),
+    }
+}
+
+fn data(syntax: Syntax, bytes: &[u8]) -> String {
+    let bytes: Vec<_> = bytes.iter().map(|byte| format!("{byte:#04x}")).collect();
+    match syntax {
+        Syntax::Cowboy => format!("DB {}", bytes.join(", ")),
+        Syntax::Octo => bytes.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    /// Opcodes that decode to an instruction
+    fn valid_ops() -> impl Iterator<Item = (u16, Instruction)> {
+        (0..=u16::MAX).filter_map(|op| Instruction::decode(op).map(|instruction| (op, instruction)))
+    }
+
+    #[test]
+    fn encode_inverts_decode() {
+        for (op, instruction) in valid_ops() {
+            assert_eq!(instruction.encode(), op, "{instruction:?}");
+        }
+    }
+
+    #[test]
+    fn octo_syntax_assembles_back() {
+        for (op, instruction) in valid_ops() {
+            let text = instruction.format(Syntax::Octo, 0x1234, &|_| None);
+            // Skips need an instruction to skip
+            let source = format!(": main {text} clear");
+            let rom = match assemble(&source) {
+                Ok(program) => program.rom,
+                Err(e) => panic!("{source}: {e}"),
+            };
+            let mut expected = op.to_be_bytes().to_vec();
+            if instruction == Instruction::LoadLongI {
+                expected.extend([0x12, 0x34]);
+            }
+            assert_eq!(rom[..expected.len()], expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn format() {
+        let label = |addr: u16| (addr == 0x300).then(|| "sprite".to_string());
+        let cases = [
+            (0x00c4, "SCD 0x4", "scroll-down 4"),
+            (0x00e0, "CLS", "clear"),
+            (0x1300, "JP sprite", "jump sprite"),
+            (0x2246, "CALL 0x246", ":call 0x246"),
+            (0x3a12, "SE VA, 0x12", "if va != 0x12 then"),
+            (0x4a12, "SNE VA, 0x12", "if va == 0x12 then"),
+            (0x5120, "SE V1, V2", "if v1 != v2 then"),
+            (0x5123, "LOAD V1-V2", "load v1 - v2"),
+            (0x6f01, "LD VF, 0x01", "vf := 0x01"),
+            (0x8125, "SUB V1, V2", "v1 -= v2"),
+            (0x8127, "SUBN V1, V2", "v1 =- v2"),
+            (0x812e, "SHL V1, V2", "v1 <<= v2"),
+            (0x9120, "SNE V1, V2", "if v1 == v2 then"),
+            (0xa300, "LD I, sprite", "i := sprite"),
+            (0xb210, "JP V0, 0x210", "jump0 0x210"),
+            (0xc10f, "RND V1, 0x0f", "v1 := random 0x0f"),
+            (0xd125, "DRW V1, V2, 0x5", "sprite v1 v2 5"),
+            (0xe19e, "SKP V1", "if v1 -key then"),
+            (0xe1a1, "SKNP V1", "if v1 key then"),
+            (0xf000, "LD I, 0x1234", "i := long 0x1234"),
+            (0xf201, "PLANE 0x2", "plane 2"),
+            (0xf10a, "LD V1, K", "v1 := key"),
+            (0xf130, "LD HF, V1", "i := bighex v1"),
+            (0xf13a, "PITCH V1", "pitch := v1"),
+            (0xf185, "LD V1, R", "loadflags v1"),
+        ];
+        for (op, cowboy, octo) in cases {
+            let instruction = Instruction::decode(op).unwrap();
+            assert_eq!(instruction.format(Syntax::Cowboy, 0x1234, &label), cowboy);
+            assert_eq!(instruction.format(Syntax::Octo, 0x1234, &label), octo);
+        }
+
+        for op in [0x0000, 0x5121, 0x8128, 0xe100, 0xf003, 0xf1ff] {
+            assert_eq!(Instruction::decode(op), None, "{op:#06x}");
+        }
+    }
+
+    #[test]
+    fn rom_listing() {
+        let rom = [
+            0xa2, 0x0c, // i := sprite
+            0x22, 0x0a, // :call sub
+            0x30, 0x00, // if v0 != 0 then
+            0x12, 0x08, // jump loop
+            0x12, 0x08, // loop: jump loop
+            0x00, 0xee, // sub: return
+            0xff, 0x81, // sprite data, one of them a valid opcode
+            0x00, 0xe0, // only reached through BNNN, so data too
+        ];
+        let octo = disassemble_rom(&rom, Syntax::Octo, &Symbols::new());
+        assert_eq!(
+            octo,
+            ": main
+    i := data_20c
+    :call sub_20a
+    if v0 != 0x00 then
+    jump label_208
+: label_208
+    jump label_208
+: sub_20a
+    return
+: data_20c
+    0xff 0x81 0x00 0xe0
+"
+        );
+
+        let mut symbols = Symbols::new();
+        symbols.insert("tile".to_string(), 0x20c);
+        let cowboy = disassemble_rom(&rom, Syntax::Cowboy, &symbols);
+        assert!(cowboy.starts_with("start:\n    LD I, tile\n    CALL sub_20a\n"), "{cowboy}");
+        assert!(cowboy.ends_with("tile:\n    DB 0xff, 0x81, 0x00, 0xe0\n"), "{cowboy}");
+    }
+
+    #[test]
+    fn labels_inside_instructions() {
+        // The jump lands in the middle of the long load, so it cannot get a
+        // label, and the unreachable bytes after it are data
+        let rom = [0xf0, 0x00, 0x12, 0x02, 0x12, 0x02, 0x00, 0xfd, 0x60];
+        let listing = disassemble_rom(&rom, Syntax::Octo, &Symbols::new());
+        assert_eq!(
+            listing,
+            ": main
+    i := long 0x1202
+    jump 0x202
+    0x00 0xfd 0x60
+"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use chip8em::{CHIP8_HIRES_HEIGHT, CHIP8_HIRES_WIDTH, Chip8};
+
+/// Brightness of plane 1 and plane 2 of every pixel, from 0 to 255
+pub type Pixels = [[[u8; 2]; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT];
+
+/// How the framebuffer is turned into the picture shown. Games erase and
+/// redraw their sprites with XOR every frame, so they flicker when shown as
+/// they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AntiFlicker {
+    /// Shows every change to the framebuffer right away
+    Off,
+    /// Shows pixels lit in either of the last two frames
+    Blend,
+    /// Lets pixels fade out, losing `decay` of their brightness every frame
+    Fade(f32),
+    /// Only shows the framebuffer as it is at vblank, never a frame that is
+    /// still being drawn
+    Vblank,
+}
+
+impl AntiFlicker {
+    /// Brightness lost per frame by [`AntiFlicker::Fade`] if not given
+    pub const DEFAULT_DECAY: f32 = 0.3;
+
+    pub const NAMES: [(&'static str, Self); 4] = [
+        ("off", Self::Off),
+        ("blend", Self::Blend),
+        ("fade", Self::Fade(Self::DEFAULT_DECAY)),
+        ("vblank", Self::Vblank),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, mode)| *mode)
+    }
+
+    /// Parses a mode name, with the decay given as `fade:<0..1>`. A decay
+    /// of 0 would never dim a pixel, so it is rejected.
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        match spec.split_once(':') {
+            Some(("fade", decay)) => {
+                let decay = decay.parse().ok().filter(|&decay| decay > 0.0 && decay <= 1.0)?;
+                Some(Self::Fade(decay))
+            }
+            Some(_) => None,
+            None => Self::from_name(spec),
+        }
+    }
+}
+
+/// The picture shown for the framebuffer, see [`AntiFlicker`]
+pub struct Display {
+    mode: AntiFlicker,
+    pixels: Pixels,
+    /// Pixels in use when the picture was last updated
+    size: [u32; 2],
+    /// The framebuffer at the previous vblank, for blending
+    previous: [[u8; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT],
+    /// Whether the picture changed since the last [`Display::take_dirty`]
+    dirty: bool,
+}
+
+impl Display {
+    pub fn new(mode: AntiFlicker) -> Self {
+        Self {
+            mode,
+            pixels: [[[0; 2]; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT],
+            size: [0; 2],
+            previous: [[0; CHIP8_HIRES_WIDTH]; CHIP8_HIRES_HEIGHT],
+            dirty: true,
+        }
+    }
+
+    pub fn pixels(&self) -> &Pixels {
+        &self.pixels
+    }
+
+    /// Width and height of the picture in pixels
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Returns whether the picture changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Updates the picture after the framebuffer changed in the middle of a
+    /// frame, e.g. while single-stepping
+    pub fn draw(&mut self, chip8: &Chip8) {
+        if self.mode == AntiFlicker::Off {
+            self.show(chip8);
+        }
+    }
+
+    /// Updates the picture at the end of a frame, when the timers tick
+    pub fn vblank(&mut self, chip8: &Chip8) {
+        let framebuffer = chip8.framebuffer();
+        match self.mode {
+            AntiFlicker::Off | AntiFlicker::Vblank => self.show(chip8),
+            AntiFlicker::Blend => {
+                let mut pixels = self.pixels;
+                for ((row, current), previous) in
+                    pixels.iter_mut().zip(framebuffer).zip(&self.previous)
+                {
+                    for ((pixel, current), previous) in row.iter_mut().zip(current).zip(previous) {
+                        *pixel = brightness(current | previous);
+                    }
+                }
+                self.set(pixels, chip8);
+            }
+            AntiFlicker::Fade(decay) => {
+                let mut pixels = self.pixels;
+                for (row, current) in pixels.iter_mut().zip(framebuffer) {
+                    for (pixel, &current) in row.iter_mut().zip(current) {
+                        let lit = brightness(current);
+                        for (plane, lit) in pixel.iter_mut().zip(lit) {
+                            let faded = (*plane as f32 * (1.0 - decay)) as u8;
+                            *plane = faded.max(lit);
+                        }
+                    }
+                }
+                self.set(pixels, chip8);
+            }
+        }
+        self.previous = *framebuffer;
+    }
+
+    /// Shows the framebuffer as it is, without blending in earlier frames,
+    /// e.g. after loading a state
+    pub fn show(&mut self, chip8: &Chip8) {
+        let mut pixels = self.pixels;
+        for (row, current) in pixels.iter_mut().zip(chip8.framebuffer()) {
+            for (pixel, &current) in row.iter_mut().zip(current) {
+                *pixel = brightness(current);
+            }
+        }
+        self.set(pixels, chip8);
+        self.previous = *chip8.framebuffer();
+    }
+
+    fn set(&mut self, pixels: Pixels, chip8: &Chip8) {
+        let size = [chip8.width() as u32, chip8.height() as u32];
+        if pixels != self.pixels || size != self.size {
+            self.pixels = pixels;
+            self.size = size;
+            self.dirty = true;
+        }
+    }
+}
+
+/// Full brightness in the planes a framebuffer pixel is lit in
+fn brightness(pixel: u8) -> [u8; 2] {
+    [pixel & 1, (pixel >> 1) & 1].map(|lit| lit * 255)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_spec() {
+        assert_eq!(AntiFlicker::from_spec("off"), Some(AntiFlicker::Off));
+        assert_eq!(AntiFlicker::from_spec("vblank"), Some(AntiFlicker::Vblank));
+        assert_eq!(AntiFlicker::from_spec("fade"), Some(AntiFlicker::Fade(0.3)));
+        assert_eq!(AntiFlicker::from_spec("fade:0.5"), Some(AntiFlicker::Fade(0.5)));
+        assert_eq!(AntiFlicker::from_spec("fade:1"), Some(AntiFlicker::Fade(1.0)));
+
+        for spec in ["fade:0", "fade:-0.1", "fade:1.5", "fade:x", "blend:0.5", "shimmer"] {
+            assert_eq!(AntiFlicker::from_spec(spec), None, "{spec}");
+        }
+    }
+}
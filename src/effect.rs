@@ -0,0 +1,93 @@
+/// Post-processing applied to the game screen, see `post.wgsl`. Every
+/// parameter is a strength from 0 (off) to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Effect {
+    /// Darkening between the rows of pixels
+    pub scanlines: f32,
+    /// Barrel distortion of the picture
+    pub curvature: f32,
+    /// Glow bleeding out of lit pixels
+    pub bloom: f32,
+    /// Width of the gaps between pixels, like an LCD
+    pub grid: f32,
+    /// How much of the previous frame lingers, like phosphor or a slow LCD
+    pub persistence: f32,
+}
+
+impl Effect {
+    pub const NONE: Self =
+        Self { scanlines: 0.0, curvature: 0.0, bloom: 0.0, grid: 0.0, persistence: 0.0 };
+
+    pub const PRESETS: [(&'static str, Self); 3] = [
+        ("none", Self::NONE),
+        (
+            "crt",
+            Self { scanlines: 0.5, curvature: 0.1, bloom: 0.3, grid: 0.0, persistence: 0.4 },
+        ),
+        ("lcd", Self { grid: 0.15, persistence: 0.6, ..Self::NONE }),
+    ];
+
+    pub const PARAMS: [&'static str; 5] =
+        ["scanlines", "curvature", "bloom", "grid", "persistence"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::PRESETS.iter().find(|(n, _)| *n == name).map(|(_, effect)| *effect)
+    }
+
+    /// Parses a preset name, optionally followed by parameters to override,
+    /// such as `crt:curvature=0,bloom=0.5`
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        let mut effect = Self::from_name(name)?;
+        for param in params.split(',').filter(|param| !param.is_empty()) {
+            let (param, value) = param.split_once('=')?;
+            let value = value.parse().ok().filter(|value| (0.0..=1.0).contains(value))?;
+            *effect.param_mut(param)? = value;
+        }
+        Some(effect)
+    }
+
+    fn param_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "scanlines" => Some(&mut self.scanlines),
+            "curvature" => Some(&mut self.curvature),
+            "bloom" => Some(&mut self.bloom),
+            "grid" => Some(&mut self.grid),
+            "persistence" => Some(&mut self.persistence),
+            _ => None,
+        }
+    }
+
+    /// Whether the screen can be drawn without a post-processing pass
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_spec() {
+        for (name, preset) in Effect::PRESETS {
+            assert_eq!(Effect::from_spec(name), Some(preset));
+        }
+        let crt = Effect::from_name("crt").unwrap();
+        assert_eq!(
+            Effect::from_spec("crt:curvature=0,bloom=0.5"),
+            Some(Effect { curvature: 0.0, bloom: 0.5, ..crt })
+        );
+        assert_eq!(Effect::from_spec("none:grid=1"), Some(Effect { grid: 1.0, ..Effect::NONE }));
+        assert_eq!(Effect::from_spec("lcd:"), Effect::from_name("lcd"));
+        for param in Effect::PARAMS {
+            let effect = Effect::from_spec(&format!("none:{param}=0.25")).unwrap();
+            assert!(!effect.is_none(), "{param}");
+        }
+
+        for bad in ["", "vhs", "crt:glow=0.5", "crt:bloom", "crt:bloom=high", "crt:bloom=1.5"] {
+            assert_eq!(Effect::from_spec(bad), None, "{bad}");
+        }
+        assert_eq!(Effect::from_spec("crt:bloom=-0.1"), None);
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+/// Faults raised by the interpreter.
+///
+/// Runtime faults record the address of the offending instruction and its
+/// opcode so the frontend can report where the ROM went wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    RomTooLarge { size: usize, max: usize },
+    InvalidOpcode { pc: usize, op: u16 },
+    StackOverflow { pc: usize, op: u16 },
+    StackUnderflow { pc: usize, op: u16 },
+    MemoryOutOfBounds { pc: usize, op: u16, addr: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RomTooLarge { size, max } => {
+                write!(f, "ROM is {size} bytes, at most {max} bytes fit in memory")
+            }
+            Self::InvalidOpcode { pc, op } => {
+                write!(f, "invalid instruction {op:#06x} at {pc:#05x}")
+            }
+            Self::StackOverflow { pc, op } => {
+                write!(f, "stack overflow by {op:#06x} at {pc:#05x}")
+            }
+            Self::StackUnderflow { pc, op } => {
+                write!(f, "stack underflow by {op:#06x} at {pc:#05x}")
+            }
+            Self::MemoryOutOfBounds { pc, op, addr } => {
+                write!(f, "{op:#06x} at {pc:#05x} accessed memory out of bounds at {addr:#x}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// Reasons a save state could not be restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    TrailingData,
+    Invalid,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a save state"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {version}")
+            }
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::TrailingData => write!(f, "save state has trailing data"),
+            Self::Invalid => write!(f, "save state holds an impossible machine state"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Error in Octo source or a symbol file, with the line it was found on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Fault raised while executing a single instruction, before the PC and
+/// opcode are attached to it.
+pub(crate) enum Fault {
+    InvalidOpcode,
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds(usize),
+}
+
+impl Fault {
+    pub(crate) fn at(self, pc: usize, op: u16) -> Chip8Error {
+        match self {
+            Self::InvalidOpcode => Chip8Error::InvalidOpcode { pc, op },
+            Self::StackOverflow => Chip8Error::StackOverflow { pc, op },
+            Self::StackUnderflow => Chip8Error::StackUnderflow { pc, op },
+            Self::MemoryOutOfBounds(addr) => Chip8Error::MemoryOutOfBounds { pc, op, addr },
+        }
+    }
+}
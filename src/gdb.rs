@@ -0,0 +1,98 @@
+//! Serves [`GdbSession`] on a TCP port, so ROMs can be debugged with gdb or
+//! an editor's debugger UI.
+//!
+//! Both the listener and the connection are non-blocking and polled from
+//! the main thread, which owns the machine.
+
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener, TcpStream},
+};
+
+use chip8em::{Break, Chip8, GdbControl, GdbSession};
+
+pub struct GdbStub {
+    listener: TcpListener,
+    /// The connected debugger, one at a time
+    session: Option<GdbSession<TcpStream>>,
+}
+
+impl GdbStub {
+    /// Starts listening on `port` on localhost
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Waiting for gdb on {}", listener.local_addr()?);
+        Ok(Self { listener, session: None })
+    }
+
+    /// Accepts a waiting debugger and handles the packets received since
+    /// the last call
+    pub fn poll(&mut self, chip8: &mut Chip8) -> Option<GdbControl> {
+        let mut control = None;
+        if self.session.is_none() {
+            match self.listener.accept() {
+                Ok((stream, addr)) => match stream.set_nonblocking(true) {
+                    Ok(()) => {
+                        log::info!("gdb connected from {addr}");
+                        self.session = Some(GdbSession::new(stream));
+                        control = Some(GdbControl::Pause);
+                    }
+                    Err(e) => log::warn!("Unable to set up gdb connection: {e}"),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => log::warn!("Unable to accept gdb connection: {e}"),
+            }
+        }
+
+        let session = self.session.as_mut()?;
+        match session.poll(chip8) {
+            Ok(Some(GdbControl::Detach)) => {
+                log::info!("gdb detached");
+                self.session = None;
+                Some(GdbControl::Detach)
+            }
+            Ok(polled) => polled.or(control),
+            Err(e) => {
+                self.disconnect(e);
+                control
+            }
+        }
+    }
+
+    /// Tells gdb that a step requested with [`GdbControl::Step`] finished
+    pub fn report_step(&mut self) {
+        self.report(GdbSession::report_step);
+    }
+
+    /// Tells gdb that execution was paused from the frontend
+    pub fn report_pause(&mut self) {
+        self.report(GdbSession::report_pause);
+    }
+
+    /// Tells gdb that execution stopped at a breakpoint
+    pub fn report_break(&mut self, hit: Break) {
+        self.report(|session| session.report_break(hit));
+    }
+
+    /// Tells gdb that execution stopped on a fault
+    pub fn report_fault(&mut self) {
+        self.report(GdbSession::report_fault);
+    }
+
+    fn report(&mut self, f: impl FnOnce(&mut GdbSession<TcpStream>) -> io::Result<()>) {
+        if let Some(session) = &mut self.session
+            && let Err(e) = f(session)
+        {
+            self.disconnect(e);
+        }
+    }
+
+    fn disconnect(&mut self, error: io::Error) {
+        if error.kind() != io::ErrorKind::UnexpectedEof {
+            log::warn!("gdb connection failed: {error}");
+        }
+        log::info!("gdb disconnected");
+        self.session = None;
+    }
+}
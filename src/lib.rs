@@ -0,0 +1,31 @@
+//! CHIP-8, SUPER-CHIP and XO-CHIP interpreter core.
+//!
+//! The core has no notion of wall-clock time, windows or audio devices. The
+//! embedder calls [`Chip8::step`] at the desired clock rate and
+//! [`Chip8::tick_timers`] at 60Hz, forwards key events and reads back the
+//! framebuffer and sound state.
+
+mod assembler;
+mod breakpoint;
+mod chip8;
+mod disasm;
+mod error;
+mod quirks;
+mod rng;
+mod rsp;
+mod symbols;
+mod trace;
+
+pub use assembler::{Program, assemble};
+pub use breakpoint::{Break, Breakpoint, Register};
+pub use chip8::{
+    CHIP8_BIG_FONTSET, CHIP8_FONTSET, CHIP8_HEIGHT, CHIP8_HIRES_HEIGHT, CHIP8_HIRES_WIDTH,
+    CHIP8_MEM, CHIP8_WIDTH, Chip8, DEFAULT_CYCLES_PER_FRAME,
+};
+pub use disasm::{Instruction, InstructionClass, Syntax, disassemble, disassemble_rom};
+pub use error::{AsmError, Chip8Error, StateError};
+pub use quirks::{IndexIncrement, Quirks};
+pub use rng::{Rng, random_seed};
+pub use rsp::{GdbControl, GdbSession};
+pub use symbols::Symbols;
+pub use trace::{TraceEntry, TraceFilter, TraceOutput, Tracer};
@@ -1,31 +1,437 @@
-use std::{env, fs, io::Read};
+use std::{
+    env, fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
 
 use winit::event_loop::EventLoop;
 
-use crate::app::App;
+use chip8em::{
+    Breakpoint, Chip8, DEFAULT_CYCLES_PER_FRAME, InstructionClass, Quirks, Rng, Symbols, Syntax,
+    TraceFilter, TraceOutput, Tracer,
+};
+
+use crate::{
+    app::{App, Config},
+    display::AntiFlicker,
+    effect::Effect,
+    gdb::GdbStub,
+    palette::Palette,
+    renderer::Scaling,
+    scheduler::Speed,
+};
 
-mod chip8;
 mod renderer;
+mod rewind;
 mod app;
-mod time;
+mod audio;
+mod scheduler;
+mod debugger;
+mod gdb;
+mod palette;
+mod effect;
+mod display;
+
+const USAGE: &str = "\
+Usage: chip8em [run] [options] <rom>
+       chip8em asm [-o <out.ch8>] <source.8o>
+       chip8em disasm [--syntax <cowboy|octo>] [--symbols <file>] <rom>
+
+ROMs ending in .8o are assembled before they are run. The asm subcommand
+writes the binary and a symbol file next to it. Symbols are used to label
+addresses in the debugger and in breakpoints, and are read from <rom>.sym
+unless --symbols is given.
+
+Options:
+  --quirks <profile>        Quirk profile: vip, chip48, schip, xochip or octo
+  --quirk <name>[=on|off]   Turn a quirk of the profile on or off, may be
+                            repeated: vf_reset, shift_vx, clip_sprites,
+                            jump_vx or display_wait
+  --seed <n>                Seed for the CXNN random number generator
+  --cycles-per-frame <n>    Instructions executed per 60Hz frame (default 8)
+  --speed <1|2|4|max>       Fast-forward factor (default 1)
+  --paused                  Start paused
+  --palette <palette>       Colors: default, octo, lcd, amber, high-contrast,
+                            or hex colors <bg>,<fg> or <bg>,<plane 1>,
+                            <plane 2>,<both planes>
+  --effect <effect>         Post-processing: none, crt or lcd, optionally
+                            followed by :<param>=<0..1>,... to tune scanlines,
+                            curvature, bloom, grid or persistence
+  --anti-flicker <mode>     Hide flickering sprites: off, blend (the last two
+                            frames), fade[:<decay>] (decay in (0, 1] per frame,
+                            default 0.3) or vblank (only complete frames)
+  --scale <fit|integer>     Scale the game to fit the window, or by whole
+                            multiples only (default fit)
+  --symbols <file>          Symbol file with a `name 0xADDR` line per label
+  --break <spec>            Pause when a breakpoint triggers, may be repeated:
+                              <addr>           PC reaches addr
+                              op:<pattern>     next opcode matches, e.g. op:DXYN
+                              read:<a>[-<b>]   an instruction reads memory
+                              write:<a>[-<b>]  an instruction writes memory
+                              reg:<V0..VF|I>   an instruction changes a register
+                            with addresses as a label or 0x-prefixed hex
+  --gdb <port>              Start paused and wait for gdb on localhost:<port>
+  --trace <log|file>        Trace executed instructions through the log
+                            (RUST_LOG=chip8em::trace=trace) or to a file
+  --trace-range <a>-<b>     Only trace instructions in the address range
+  --trace-class <classes>   Only trace these comma separated classes: flow,
+                            skip, alu, index, memory, display, input, timer
+                            or audio
+  --trace-ring <n>          Keep the last n traced instructions and only
+                            write them out when the ROM faults";
+
+struct Options {
+    rom: PathBuf,
+    quirks: Quirks,
+    seed: Option<u64>,
+    cycles_per_frame: u32,
+    speed: Speed,
+    paused: bool,
+    palette: Palette,
+    effect: Effect,
+    anti_flicker: AntiFlicker,
+    scaling: Scaling,
+    symbols: Option<PathBuf>,
+    /// Breakpoint specs, parsed once the symbols are loaded
+    breakpoints: Vec<String>,
+    gdb_port: Option<u16>,
+    /// Trace destination, `log` or a file
+    trace: Option<String>,
+    /// Trace address range, parsed once the symbols are loaded
+    trace_range: Option<String>,
+    trace_classes: Vec<InstructionClass>,
+    trace_ring: Option<usize>,
+}
 
 fn main() {
     env_logger::init();
 
-    let mut args = env::args();
-    args.next();
-
-    let Some(path) = args.next() else {
-        eprintln!("Need a ROM path");
+    let command = env::args().nth(1);
+    let result = match command.as_deref() {
+        Some("disasm") => Some(disasm(env::args().skip(2))),
+        Some("asm") => Some(asm(env::args().skip(2))),
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Err(e) = result {
+            eprintln!("{e}\n\n{USAGE}");
+        }
         return;
+    }
+
+    let skip = if command.as_deref() == Some("run") { 2 } else { 1 };
+    let options = match parse_args(env::args().skip(skip)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return;
+        }
     };
 
-    let mut file = fs::File::open(path).expect("Unable to open file");
-    let mut content = vec![];
-    file.read_to_end(&mut content).expect("Unable to read file");
-    drop(file);
+    let (content, symbols) = match load_rom(&options.rom, options.symbols.as_deref()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    let rom_size = content.len();
+    let mut chip8 = match Chip8::new(content, options.quirks) {
+        Ok(chip8) => chip8,
+        Err(e) => {
+            eprintln!("Unable to load ROM: {e}");
+            return;
+        }
+    };
+
+    // Logged so a run can be reproduced with --seed
+    let seed = options.seed.unwrap_or_else(chip8em::random_seed);
+    log::info!("RNG seed {seed}");
+    chip8.set_rng(Rng::seeded(seed));
+    for spec in &options.breakpoints {
+        match Breakpoint::from_spec(spec, &symbols) {
+            Some(breakpoint) => chip8.add_breakpoint(breakpoint),
+            None => {
+                eprintln!("Invalid breakpoint {spec}\n\n{USAGE}");
+                return;
+            }
+        }
+    }
+
+    match tracer(&options, &symbols) {
+        Ok(tracer) => {
+            chip8.set_tracer(tracer);
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return;
+        }
+    }
+
+    let gdb = match options.gdb_port.map(GdbStub::listen).transpose() {
+        Ok(gdb) => gdb,
+        Err(e) => {
+            eprintln!("Unable to listen for gdb: {e}");
+            return;
+        }
+    };
+
+    let config = Config {
+        palette: options.palette,
+        effect: options.effect,
+        anti_flicker: options.anti_flicker,
+        scaling: options.scaling,
+        cycles_per_frame: options.cycles_per_frame,
+        speed: options.speed,
+        paused: options.paused,
+        symbols,
+        rom_size,
+        gdb,
+    };
 
     let event_loop = EventLoop::with_user_event().build().unwrap();
-    let mut app = App::new([0.0, 0.25, 0.0], content);
+    let mut app = App::new(chip8, options.rom, config);
     event_loop.run_app(&mut app).unwrap();
 }
+
+/// Creates the tracer asked for by the `--trace` options, if any
+fn tracer(options: &Options, symbols: &Symbols) -> Result<Option<Tracer>, String> {
+    let range = match &options.trace_range {
+        Some(spec) => {
+            let range = symbols.resolve_range(spec);
+            Some(range.ok_or_else(|| format!("Invalid trace range {spec}"))?)
+        }
+        None => None,
+    };
+    let filter = TraceFilter { range, classes: options.trace_classes.clone() };
+    let tracing =
+        options.trace.is_some() || options.trace_ring.is_some() || filter != TraceFilter::default();
+    if !tracing {
+        return Ok(None);
+    }
+
+    let output = match options.trace.as_deref() {
+        None | Some("log") => TraceOutput::Log,
+        Some(path) => {
+            let file =
+                fs::File::create(path).map_err(|e| format!("Unable to create {path}: {e}"))?;
+            TraceOutput::Writer(Box::new(BufWriter::new(file)))
+        }
+    };
+    let mut tracer = Tracer::new(filter, output, options.trace_ring);
+    tracer.set_symbols(symbols.clone());
+    Ok(Some(tracer))
+}
+
+/// Reads a ROM and its symbols, assembling it first if it is Octo source
+fn load_rom(path: &Path, symbols: Option<&Path>) -> Result<(Vec<u8>, Symbols), String> {
+    if path.extension().is_some_and(|ext| ext == "8o") {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+        let program = chip8em::assemble(&source).map_err(|e| format!("{}: {e}", path.display()))?;
+        let symbols = match symbols {
+            Some(symbols) => load_symbols(symbols)?,
+            None => program.symbols,
+        };
+        return Ok((program.rom, symbols));
+    }
+
+    let rom = fs::read(path).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+    let symbols = match symbols {
+        Some(symbols) => load_symbols(symbols)?,
+        None => {
+            let default = path.with_extension("sym");
+            if default.exists() {
+                load_symbols(&default)?
+            } else {
+                Symbols::new()
+            }
+        }
+    };
+    Ok((rom, symbols))
+}
+
+fn load_symbols(path: &Path) -> Result<Symbols, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+    Symbols::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Assembles Octo source into a ROM and a symbol file with one
+/// `name 0xADDR` line per label
+fn asm(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut source = None;
+    let mut out = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => out = Some(PathBuf::from(args.next().ok_or("-o needs a value")?)),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ => source = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let source = source.ok_or("Need a source path")?;
+    let out = out.unwrap_or_else(|| source.with_extension("ch8"));
+    let text = fs::read_to_string(&source)
+        .map_err(|e| format!("Unable to read {}: {e}", source.display()))?;
+    let program = chip8em::assemble(&text).map_err(|e| format!("{}: {e}", source.display()))?;
+
+    let sym = out.with_extension("sym");
+    fs::write(&out, &program.rom).map_err(|e| format!("Unable to write {}: {e}", out.display()))?;
+    fs::write(&sym, program.symbols.to_string())
+        .map_err(|e| format!("Unable to write {}: {e}", sym.display()))?;
+    Ok(())
+}
+
+/// Prints a disassembly of the ROM, following the code from the entry point
+fn disasm(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut rom = None;
+    let mut syntax = Syntax::Cowboy;
+    let mut symbols = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let name = args.next().ok_or("--syntax needs a value")?;
+                syntax =
+                    Syntax::from_name(&name).ok_or_else(|| format!("Unknown syntax {name}"))?;
+            }
+            "--symbols" => {
+                symbols = Some(PathBuf::from(args.next().ok_or("--symbols needs a value")?));
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let rom = rom.ok_or("Need a ROM path")?;
+    let (content, symbols) = load_rom(&rom, symbols.as_deref())?;
+    print!("{}", chip8em::disassemble_rom(&content, syntax, &symbols));
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom = None;
+    let mut options = Options {
+        rom: PathBuf::new(),
+        quirks: Quirks::default(),
+        seed: None,
+        cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+        speed: Speed::Normal,
+        paused: false,
+        palette: Palette::THEMES[0].1,
+        effect: Effect::NONE,
+        anti_flicker: AntiFlicker::Off,
+        scaling: Scaling::Fit,
+        symbols: None,
+        breakpoints: vec![],
+        gdb_port: None,
+        trace: None,
+        trace_range: None,
+        trace_classes: vec![],
+        trace_ring: None,
+    };
+
+    // Applied once the profile is known, whatever the order of the options
+    let mut quirk_overrides = vec![];
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--quirk" => quirk_overrides.push(value()?),
+            "--quirks" => {
+                let name = value()?;
+                options.quirks = Quirks::from_name(&name).ok_or_else(|| {
+                    let names: Vec<_> = Quirks::PRESETS.iter().map(|(name, _)| *name).collect();
+                    format!("Unknown quirk profile {name}, expected one of: {}", names.join(", "))
+                })?;
+            }
+            "--seed" => {
+                let seed = value()?;
+                options.seed = Some(seed.parse().map_err(|_| format!("Invalid seed {seed}"))?);
+            }
+            "--cycles-per-frame" => {
+                let cycles = value()?;
+                options.cycles_per_frame = cycles
+                    .parse()
+                    .ok()
+                    .filter(|&cycles| cycles > 0)
+                    .ok_or_else(|| format!("Invalid cycles per frame {cycles}"))?;
+            }
+            "--speed" => {
+                let speed = value()?;
+                options.speed =
+                    Speed::from_name(&speed).ok_or_else(|| format!("Unknown speed {speed}"))?;
+            }
+            "--paused" => options.paused = true,
+            "--palette" => {
+                let spec = value()?;
+                options.palette =
+                    Palette::from_spec(&spec).ok_or_else(|| format!("Invalid palette {spec}"))?;
+            }
+            "--effect" => {
+                let spec = value()?;
+                options.effect = Effect::from_spec(&spec).ok_or_else(|| {
+                    let names: Vec<_> = Effect::PRESETS.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "Invalid effect {spec}, expected one of {} followed by optional \
+                         <param>=<0..1> for {}",
+                        names.join(", "),
+                        Effect::PARAMS.join(", ")
+                    )
+                })?;
+            }
+            "--anti-flicker" => {
+                let mode = value()?;
+                options.anti_flicker = AntiFlicker::from_spec(&mode)
+                    .ok_or_else(|| format!("Invalid anti-flicker mode {mode}"))?;
+            }
+            "--scale" => {
+                let name = value()?;
+                options.scaling =
+                    Scaling::from_name(&name).ok_or_else(|| format!("Unknown scaling {name}"))?;
+            }
+            "--gdb" => {
+                let port = value()?;
+                options.gdb_port = Some(port.parse().map_err(|_| format!("Invalid port {port}"))?);
+                options.paused = true;
+            }
+            "--symbols" => options.symbols = Some(PathBuf::from(value()?)),
+            "--break" => options.breakpoints.push(value()?),
+            "--trace" => options.trace = Some(value()?),
+            "--trace-range" => options.trace_range = Some(value()?),
+            "--trace-class" => {
+                for name in value()?.split(',') {
+                    let class = InstructionClass::from_name(name)
+                        .ok_or_else(|| format!("Unknown instruction class {name}"))?;
+                    options.trace_classes.push(class);
+                }
+            }
+            "--trace-ring" => {
+                let len = value()?;
+                let len = len.parse().map_err(|_| format!("Invalid trace length {len}"))?;
+                options.trace_ring = Some(len);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+
+    for spec in quirk_overrides {
+        let (name, on) = match spec.split_once('=') {
+            Some((name, "on")) => (name, true),
+            Some((name, "off")) => (name, false),
+            Some(_) => return Err(format!("Invalid quirk {spec}, expected <name>[=on|off]")),
+            None => (spec.as_str(), true),
+        };
+        let flag = options.quirks.flag_mut(name).ok_or_else(|| {
+            format!("Unknown quirk {name}, expected one of: {}", Quirks::FLAGS.join(", "))
+        })?;
+        *flag = on;
+    }
+
+    options.rom = rom.ok_or("Need a ROM path")?;
+    Ok(options)
+}
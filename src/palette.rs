@@ -0,0 +1,115 @@
+//! Display colors, given as sRGB hex like the rest of the CHIP-8 world
+
+/// Colors for pixel values 0 to 3: the background, then pixels lit in plane
+/// 1, plane 2 and both planes. 1-bit ROMs only use the first two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [u32; 4]);
+
+impl Palette {
+    pub const THEMES: [(&'static str, Self); 5] = [
+        ("default", Self([0x000000, 0x008900, 0x956c00, 0xcbcbcb])),
+        // Octo's own defaults
+        ("octo", Self([0x996600, 0xffcc00, 0xff6600, 0x662200])),
+        ("lcd", Self([0x9bbc0f, 0x0f380f, 0x306230, 0x8bac0f])),
+        ("amber", Self([0x140c00, 0xffb000, 0x9c5a00, 0xffe4a0])),
+        ("high-contrast", Self([0x000000, 0xffffff, 0xffff00, 0x00ffff])),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::THEMES.iter().find(|(n, _)| *n == name).map(|(_, palette)| *palette)
+    }
+
+    /// Parses a theme name, or a comma separated list of two or four hex
+    /// colors such as `#ffcc00` or `#fc0`. With two colors, both planes use
+    /// the foreground.
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        if let Some(palette) = Self::from_name(spec) {
+            return Some(palette);
+        }
+
+        let colors = spec.split(',').map(parse_color).collect::<Option<Vec<_>>>()?;
+        match colors[..] {
+            [background, foreground] => {
+                Some(Self([background, foreground, foreground, foreground]))
+            }
+            [background, plane1, plane2, both] => Some(Self([background, plane1, plane2, both])),
+            _ => None,
+        }
+    }
+
+    /// Name of the theme this palette is, if any
+    pub fn name(&self) -> Option<&'static str> {
+        Self::THEMES.iter().find(|(_, palette)| palette == self).map(|(name, _)| *name)
+    }
+
+    /// The theme after this one, wrapping around
+    pub fn next(&self) -> Self {
+        let i = Self::THEMES.iter().position(|(_, palette)| palette == self);
+        let next = i.map_or(0, |i| (i + 1) % Self::THEMES.len());
+        Self::THEMES[next].1
+    }
+
+    /// Colors from 0 to 1, still sRGB encoded, for render targets that do
+    /// not encode their output
+    pub fn rgb(&self) -> [[f32; 3]; 4] {
+        self.0.map(|color| [16, 8, 0].map(|shift| ((color >> shift) & 0xff) as f32 / 255.0))
+    }
+
+    /// Colors in linear RGB, as sRGB render targets expect them
+    pub fn linear(&self) -> [[f32; 3]; 4] {
+        self.rgb().map(|color| {
+            color.map(|c| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) })
+        })
+    }
+}
+
+/// Parses an `rrggbb` color or its `rgb` shorthand, with an optional
+/// leading `#`
+fn parse_color(color: &str) -> Option<u32> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        // Each digit is doubled, so `fff` is white
+        3 => u32::from_str_radix(hex, 16).ok().map(|rgb| {
+            let channel = |shift: u32| ((rgb >> shift) & 0xf) * 0x11;
+            channel(8) << 16 | channel(4) << 8 | channel(0)
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_spec() {
+        assert_eq!(Palette::from_spec("lcd"), Palette::from_name("lcd"));
+        assert_eq!(
+            Palette::from_spec("#000000,#ffcc00"),
+            Some(Palette([0x000000, 0xffcc00, 0xffcc00, 0xffcc00]))
+        );
+        assert_eq!(
+            Palette::from_spec("102030,a0b0c0,#123456,fff"),
+            Some(Palette([0x102030, 0xa0b0c0, 0x123456, 0xffffff]))
+        );
+        let shorthand = Palette([0x000000, 0xff8800, 0xff8800, 0xff8800]);
+        assert_eq!(Palette::from_spec("#000,#f80"), Some(shorthand));
+
+        for spec in [
+            "sepia",
+            "000000",
+            "000000,ffffff,ffffff",
+            "000000,1ffffff",
+            "000000,ffff",
+            "000000,",
+            "000000,+fffff",
+            "000000,gggggg",
+        ] {
+            assert_eq!(Palette::from_spec(spec), None, "{spec}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// Post-processing of the drawn screen, simulating a CRT or LCD
+
+const PI: f32 = 3.14159265;
+
+struct Post {
+	// Left, top, right and bottom edges of the game in texture coordinates
+	viewport: vec4<f32>,
+	// Pixels of the current CHIP-8 resolution
+	size: vec2<f32>,
+	_padding: vec2<f32>,
+	background: vec4<f32>,
+	scanlines: f32,
+	curvature: f32,
+	bloom: f32,
+	grid: f32,
+	persistence: f32,
+};
+
+@group(0) @binding(0) var<uniform> post: Post;
+// The screen drawn this frame
+@group(0) @binding(1) var scene: texture_2d<f32>;
+// The output of the previous frame
+@group(0) @binding(2) var history: texture_2d<f32>;
+@group(0) @binding(3) var linear: sampler;
+
+struct VertexOutput {
+	@builtin(position) position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	// A single triangle covering the whole window
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.uv = uv;
+	out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	return out;
+}
+
+struct FragmentOutput {
+	@location(0) color: vec4<f32>,
+	// Copy of the color read back as the history of the next frame
+	@location(1) history: vec4<f32>,
+};
+
+fn sample(uv: vec2<f32>) -> vec3<f32> {
+	return textureSampleLevel(scene, linear, uv, 0.0).rgb;
+}
+
+fn output(color: vec3<f32>) -> FragmentOutput {
+	var out: FragmentOutput;
+	out.color = vec4<f32>(color, 1.0);
+	out.history = out.color;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+	let start = post.viewport.xy;
+	let end = post.viewport.zw;
+	if any(in.uv < start) || any(in.uv > end) {
+		return output(sample(in.uv));
+	}
+
+	// Bend the picture around the center of the game
+	let centered = (in.uv - start) / (end - start) * 2.0 - 1.0;
+	let curved = centered * (1.0 + post.curvature * centered.yx * centered.yx);
+	let position = curved * 0.5 + 0.5;
+	if any(position < vec2<f32>(0.0)) || any(position > vec2<f32>(1.0)) {
+		return output(post.background.rgb);
+	}
+
+	let uv = mix(start, end, position);
+	var color = sample(uv);
+
+	if post.bloom > 0.0 {
+		// Blur over the neighbouring CHIP-8 pixels
+		let pixel = (end - start) / post.size;
+		var glow = vec3<f32>(0.0);
+		for (var y = -2; y <= 2; y++) {
+			for (var x = -2; x <= 2; x++) {
+				let offset = vec2<f32>(f32(x), f32(y));
+				let weight = exp(-dot(offset, offset) / 2.0);
+				glow += sample(uv + offset * pixel) * weight;
+			}
+		}
+		// The weights add up to roughly 2 pi
+		color += glow / (2.0 * PI) * post.bloom;
+	}
+
+	// Where this fragment lies within its CHIP-8 pixel
+	let cell = fract(position * post.size);
+	color *= 1.0 - post.scanlines * (1.0 - sin(cell.y * PI));
+
+	let edge = min(cell, 1.0 - cell);
+	let gap = post.grid * 0.5;
+	if edge.x < gap || edge.y < gap {
+		color = post.background.rgb;
+	}
+
+	let previous = textureSampleLevel(history, linear, in.uv, 0.0).rgb;
+	return output(max(color, previous * post.persistence));
+}
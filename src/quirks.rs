@@ -0,0 +1,114 @@
+/// How `FX55`/`FX65` leave the index register once the transfer is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// `I` is left pointing past the last register (`I += X + 1`)
+    XPlusOne,
+    /// `I` is left pointing at the last register (`I += X`)
+    X,
+    /// `I` is not modified
+    Unchanged,
+}
+
+/// Behaviors that differ between CHIP-8 implementations.
+///
+/// ROMs are usually written against one particular interpreter, so the
+/// right profile has to be picked for each of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0
+    pub vf_reset: bool,
+    /// `8XY6`/`8XYE` shift VX in place instead of loading VY
+    pub shift_vx: bool,
+    /// Effect of `FX55`/`FX65` on I
+    pub index_increment: IndexIncrement,
+    /// Sprites are clipped at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    /// `BXNN` jumps to `XNN + VX` instead of `NNN + V0`
+    pub jump_vx: bool,
+    /// `DXYN` waits for the next timer tick before execution continues
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Self = Self {
+        vf_reset: true,
+        shift_vx: false,
+        index_increment: IndexIncrement::XPlusOne,
+        clip_sprites: true,
+        jump_vx: false,
+        display_wait: true,
+    };
+
+    pub const CHIP48: Self = Self {
+        vf_reset: false,
+        shift_vx: true,
+        index_increment: IndexIncrement::X,
+        clip_sprites: true,
+        jump_vx: true,
+        display_wait: false,
+    };
+
+    pub const SCHIP: Self = Self {
+        vf_reset: false,
+        shift_vx: true,
+        index_increment: IndexIncrement::Unchanged,
+        clip_sprites: true,
+        jump_vx: true,
+        display_wait: false,
+    };
+
+    pub const XO_CHIP: Self = Self {
+        vf_reset: false,
+        shift_vx: false,
+        index_increment: IndexIncrement::XPlusOne,
+        clip_sprites: false,
+        jump_vx: false,
+        display_wait: false,
+    };
+
+    pub const OCTO: Self = Self {
+        vf_reset: false,
+        shift_vx: false,
+        index_increment: IndexIncrement::XPlusOne,
+        clip_sprites: false,
+        jump_vx: false,
+        display_wait: false,
+    };
+
+    pub const PRESETS: [(&'static str, Self); 5] = [
+        ("vip", Self::COSMAC_VIP),
+        ("chip48", Self::CHIP48),
+        ("schip", Self::SCHIP),
+        ("xochip", Self::XO_CHIP),
+        ("octo", Self::OCTO),
+    ];
+
+    /// Names of the quirks that are either on or off
+    pub const FLAGS: [&'static str; 5] =
+        ["vf_reset", "shift_vx", "clip_sprites", "jump_vx", "display_wait"];
+
+    /// The on/off quirk called `name`, one of [`Quirks::FLAGS`]
+    pub fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "vf_reset" => Some(&mut self.vf_reset),
+            "shift_vx" => Some(&mut self.shift_vx),
+            "clip_sprites" => Some(&mut self.clip_sprites),
+            "jump_vx" => Some(&mut self.jump_vx),
+            "display_wait" => Some(&mut self.display_wait),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::COSMAC_VIP
+    }
+}
@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
+use chip8em::{CHIP8_HIRES_HEIGHT, CHIP8_HIRES_WIDTH};
 use wgpu::{Color, util::DeviceExt};
-use winit::window::Window;
+use winit::{dpi::PhysicalSize, window::Window};
+
+use crate::{display::Pixels, effect::Effect};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -45,6 +48,96 @@ macro_rules! rect {
     };
 }
 
+/// How the framebuffer texture is drawn, laid out as `Screen` in
+/// `screen.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Screen {
+    /// Colors for pixel values 0 to 3: off, plane 1, plane 2 and both
+    pub colors: [[f32; 4]; 4],
+    /// Left, top, right and bottom edges in clip space
+    pub viewport: [f32; 4],
+    /// Pixels in use for the current resolution
+    pub size: [u32; 2],
+    pub _padding: [u32; 2],
+}
+
+/// How the framebuffer is scaled to the window. The pixels stay square
+/// either way, with the rest of the window in the background color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    /// As large as fits
+    Fit,
+    /// The largest whole multiple of the framebuffer size that fits, so every
+    /// pixel is the same size
+    Integer,
+}
+
+impl Scaling {
+    pub const NAMES: [(&'static str, Self); 2] = [("fit", Self::Fit), ("integer", Self::Integer)];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, scaling)| *scaling)
+    }
+
+    /// Left, top, right and bottom edges in clip space of a `size` pixel
+    /// framebuffer, centered in the part of the window left of `right`
+    pub fn viewport(self, window: PhysicalSize<u32>, right: f32, size: [u32; 2]) -> [f32; 4] {
+        let (window_width, window_height) = (window.width as f32, window.height as f32);
+        let (width, height) = (size[0] as f32, size[1] as f32);
+        if window_width == 0.0 || window_height == 0.0 || width == 0.0 || height == 0.0 {
+            return [-1.0, 1.0, right, -1.0];
+        }
+
+        let area_width = window_width * (right + 1.0) / 2.0;
+        let mut scale = (area_width / width).min(window_height / height);
+        if self == Self::Integer && scale >= 1.0 {
+            scale = scale.floor();
+        }
+
+        // Offsets are rounded to whole window pixels to keep the edges sharp
+        let (x, y) = (width * scale, height * scale);
+        let left = ((area_width - x) / 2.0).floor();
+        let top = ((window_height - y) / 2.0).floor();
+        [
+            left / window_width * 2.0 - 1.0,
+            1.0 - top / window_height * 2.0,
+            (left + x) / window_width * 2.0 - 1.0,
+            1.0 - (top + y) / window_height * 2.0,
+        ]
+    }
+}
+
+/// Settings of the post-processing pass, laid out as `Post` in `post.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Post {
+    /// Left, top, right and bottom edges of the game in texture coordinates
+    viewport: [f32; 4],
+    size: [f32; 2],
+    _padding: [f32; 2],
+    background: [f32; 4],
+    scanlines: f32,
+    curvature: f32,
+    bloom: f32,
+    grid: f32,
+    persistence: f32,
+    _padding_end: [f32; 3],
+}
+
+/// Textures the screen is drawn into when an effect is on, sized like the
+/// window
+struct PostTargets {
+    scene: wgpu::TextureView,
+    /// Output of the last two frames, each frame writing one and reading the
+    /// other
+    history: [wgpu::TextureView; 2],
+    /// Bind group of the frame writing `history[i]`
+    bind_groups: [wgpu::BindGroup; 2],
+    /// Index of the history written next
+    frame: usize,
+}
+
 const QUAD_INDICES: &[u16] = &[
     0, 3, 1,
     0, 2, 3,
@@ -55,10 +148,26 @@ pub struct QuadRenderer {
     device: wgpu::Device,
     queue: wgpu::Queue,
     pipeline: wgpu::RenderPipeline,
+    screen_pipeline: wgpu::RenderPipeline,
+    screen_uniform: wgpu::Buffer,
+    /// The whole hires framebuffer, with the brightness of both planes in
+    /// each texel
+    screen_texture: wgpu::Texture,
+    screen_bind_group: wgpu::BindGroup,
+    /// The last settings given to [`QuadRenderer::set_screen`]
+    screen: Screen,
+    post_pipeline: wgpu::RenderPipeline,
+    post_uniform: wgpu::Buffer,
+    post_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    effect: Effect,
+    post_targets: Option<PostTargets>,
     config: wgpu::SurfaceConfiguration,
     is_surface_configured: bool,
     clear_color: wgpu::Color,
-    indices: wgpu::Buffer,
+    /// Index buffer shared by all quads, regrown when more quads are drawn
+    indices: Option<wgpu::Buffer>,
+    index_capacity: usize,
     pub window: Arc<Window>,
 }
 
@@ -157,21 +266,205 @@ impl QuadRenderer {
             cache: None,
         });
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(QUAD_INDICES),
-                usage: wgpu::BufferUsages::INDEX,
+        let screen_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screen Uniform"),
+            size: std::mem::size_of::<Screen>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let screen_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screen Texture"),
+            size: wgpu::Extent3d {
+                width: CHIP8_HIRES_WIDTH as u32,
+                height: CHIP8_HIRES_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rg8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let screen_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Screen Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let screen_view = screen_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Screen Bind Group"),
+            layout: &screen_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: screen_uniform.as_entire_binding() },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&screen_view),
+                },
+            ],
+        });
+
+        let screen_shader = device.create_shader_module(wgpu::include_wgsl!("screen.wgsl"));
+        let screen_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Screen Pipeline Layout"),
+                bind_group_layouts: &[&screen_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let screen_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Screen Pipeline"),
+            layout: Some(&screen_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &screen_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &screen_shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let post_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Uniform"),
+            size: std::mem::size_of::<Post>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let post_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Post Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_entry(1),
+                    texture_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
             });
 
+        let post_shader = device.create_shader_module(wgpu::include_wgsl!("post.wgsl"));
+        let post_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Pipeline Layout"),
+                bind_group_layouts: &[&post_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let target = wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        let post_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Pipeline"),
+            layout: Some(&post_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &post_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &post_shader,
+                entry_point: Some("fs_main"),
+                // The window and the history read back by the next frame
+                targets: &[Some(target.clone()), Some(target)],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
         Self {
             surface,
             device,
             queue,
             pipeline,
+            screen_pipeline,
+            screen_uniform,
+            screen_texture,
+            screen_bind_group,
+            screen: bytemuck::Zeroable::zeroed(),
+            post_pipeline,
+            post_uniform,
+            post_bind_group_layout,
+            sampler,
+            effect: Effect::NONE,
+            post_targets: None,
             config,
             is_surface_configured: false,
             clear_color: Color::BLACK,
-            indices: index_buffer,
+            indices: None,
+            index_capacity: 0,
             window,
         }
     }
@@ -182,17 +475,151 @@ impl QuadRenderer {
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
             self.is_surface_configured = true;
+            self.create_post_targets();
+        }
+    }
+
+    /// Turns post-processing of the screen on or off
+    pub fn set_effect(&mut self, effect: Effect) {
+        self.effect = effect;
+        self.create_post_targets();
+    }
+
+    /// (Re)creates the textures of the post-processing pass at the window
+    /// size, if it is needed
+    fn create_post_targets(&mut self) {
+        if !self.is_surface_configured || self.effect.is_none() {
+            self.post_targets = None;
+            return;
         }
+
+        let texture = |label| {
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: self.config.width,
+                    height: self.config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let scene = texture("Scene Texture");
+        let history = [texture("History Texture"), texture("History Texture")];
+
+        let bind_group = |previous: &wgpu::TextureView| {
+            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Post Bind Group"),
+                layout: &self.post_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.post_uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&scene),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(previous),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            })
+        };
+        let bind_groups = [bind_group(&history[1]), bind_group(&history[0])];
+
+        self.post_targets = Some(PostTargets { scene, history, bind_groups, frame: 0 });
+    }
+
+    /// Uploads the picture of the framebuffer
+    pub fn upload_framebuffer(&self, pixels: &Pixels) {
+        self.queue.write_texture(
+            self.screen_texture.as_image_copy(),
+            pixels.as_flattened().as_flattened(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(2 * CHIP8_HIRES_WIDTH as u32),
+                rows_per_image: None,
+            },
+            self.screen_texture.size(),
+        );
+    }
+
+    /// Whether the window encodes colors to sRGB, so they must be given in
+    /// linear RGB. Not every surface supports an sRGB format.
+    pub fn is_srgb(&self) -> bool {
+        self.config.format.is_srgb()
     }
 
-    pub fn render_quads(
-        &mut self,
-        quads: &[[Vertex; 4]],
-    ) -> Result<(), wgpu::SurfaceError> {
+    /// Sets how the framebuffer is drawn. The rest of the window is cleared
+    /// to the background color.
+    pub fn set_screen(&mut self, screen: &Screen) {
+        let [r, g, b, a] = screen.colors[0].map(f64::from);
+        self.clear_color = Color { r, g, b, a };
+        self.screen = *screen;
+        self.queue.write_buffer(&self.screen_uniform, 0, bytemuck::bytes_of(screen));
+    }
+
+    fn write_post_uniform(&self) {
+        // Clip space y points up, texture coordinates down
+        let [left, top, right, bottom] = self.screen.viewport;
+        let (u, v) = (|x: f32| (x + 1.0) / 2.0, |y: f32| (1.0 - y) / 2.0);
+        let post = Post {
+            viewport: [u(left), v(top), u(right), v(bottom)],
+            size: self.screen.size.map(|size| size as f32),
+            _padding: [0.0; 2],
+            background: self.screen.colors[0],
+            scanlines: self.effect.scanlines,
+            curvature: self.effect.curvature,
+            bloom: self.effect.bloom,
+            grid: self.effect.grid,
+            persistence: self.effect.persistence,
+            _padding_end: [0.0; 3],
+        };
+        self.queue.write_buffer(&self.post_uniform, 0, bytemuck::bytes_of(&post));
+    }
+
+    /// Makes sure the index buffer covers at least `quads` quads
+    fn reserve_indices(&mut self, quads: usize) {
+        if quads <= self.index_capacity {
+            return;
+        }
+
+        let capacity = quads.next_power_of_two();
+        let indices: Vec<u32> = (0..capacity as u32)
+            .flat_map(|quad| QUAD_INDICES.iter().map(move |&i| quad * 4 + i as u32))
+            .collect();
+        self.indices = Some(self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        }));
+        self.index_capacity = capacity;
+    }
+
+    /// Draws the framebuffer as one quad, post-processed if an effect is
+    /// on, with `quads` on top of it
+    pub fn render(&mut self, quads: &[[Vertex; 4]]) -> Result<(), wgpu::SurfaceError> {
         self.window.request_redraw();
         if !self.is_surface_configured {
             return Ok(());
         }
+        self.reserve_indices(quads.len());
+        if self.post_targets.is_some() {
+            self.write_post_uniform();
+        }
 
         let output = self.surface.get_current_texture()?;
         let view = output
@@ -204,36 +631,64 @@ impl QuadRenderer {
                     label: Some("Render encoder"),
                 });
 
+        let attachment = |view, load| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: wgpu::StoreOp::Store },
+            })
+        };
+        let clear = wgpu::LoadOp::Clear(self.clear_color);
+
+        // With an effect on, the screen is drawn offscreen and post-processed
+        // into the window before the quads go on top
+        let scene = self.post_targets.as_ref().map_or(&view, |targets| &targets.scene);
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_color),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
+                color_attachments: &[attachment(scene, clear)],
                 depth_stencil_attachment: None,
                 timestamp_writes: None,
                 occlusion_query_set: None,
             });
 
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint16);
-
-            for v in quads.iter() {
-                let vertex_buffer = self
-                    .device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Vertex Buffer"),
-                        contents: bytemuck::cast_slice(v),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    });
-                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                render_pass.draw_indexed(0..QUAD_INDICES.len() as u32, 0, 0..1);
+            render_pass.set_pipeline(&self.screen_pipeline);
+            render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+            render_pass.draw(0..4, 0..1);
+
+            if self.post_targets.is_none() {
+                self.draw_quads(&mut render_pass, quads);
+            }
+        }
+
+        if let Some(targets) = &self.post_targets {
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Post Pass"),
+                    color_attachments: &[
+                        attachment(&view, clear),
+                        attachment(&targets.history[targets.frame], clear),
+                    ],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(&self.post_pipeline);
+                render_pass.set_bind_group(0, &targets.bind_groups[targets.frame], &[]);
+                render_pass.draw(0..3, 0..1);
             }
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[attachment(&view, wgpu::LoadOp::Load)],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.draw_quads(&mut render_pass, quads);
+        }
+        if let Some(targets) = &mut self.post_targets {
+            targets.frame = 1 - targets.frame;
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -241,4 +696,25 @@ impl QuadRenderer {
 
         Ok(())
     }
+
+    fn draw_quads(&self, render_pass: &mut wgpu::RenderPass<'_>, quads: &[[Vertex; 4]]) {
+        let Some(indices) = &self.indices else {
+            return;
+        };
+        if quads.is_empty() {
+            return;
+        }
+
+        // All quads go out in a single draw, the debugger panel alone can be
+        // thousands of them
+        let vertex_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(quads),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw_indexed(0..(quads.len() * QUAD_INDICES.len()) as u32, 0, 0..1);
+    }
 }
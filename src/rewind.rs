@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+
+/// History of save states, one per frame.
+///
+/// Only the newest state is kept in full. Every older frame is stored as the
+/// XOR against the frame after it, with runs of unchanged bytes skipped, so
+/// a frame costs roughly as many bytes as the machine touched.
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Self { latest: None, deltas: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = &self.latest {
+            if latest.len() == state.len() {
+                self.deltas.push_back(encode_delta(&state, latest));
+                if self.deltas.len() > self.capacity {
+                    self.deltas.pop_front();
+                }
+            } else {
+                self.deltas.clear();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    /// Steps one frame back in history, returning the state to restore
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        let latest = self.latest.as_mut()?;
+        apply_delta(latest, &delta);
+        Some(latest)
+    }
+}
+
+/// Encodes `new ^ old` as a sequence of (skip, len, xor bytes) runs
+fn encode_delta(new: &[u8], old: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+
+    while i < new.len() {
+        let start = i;
+        while i < new.len() && new[i] == old[i] {
+            i += 1;
+        }
+        if i == new.len() {
+            break;
+        }
+        let skip = i - start;
+
+        let start = i;
+        while i < new.len() && new[i] != old[i] {
+            i += 1;
+        }
+
+        put_varint(&mut out, skip);
+        put_varint(&mut out, i - start);
+        out.extend(new[start..i].iter().zip(&old[start..i]).map(|(a, b)| a ^ b));
+    }
+
+    out
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut delta = delta;
+    let mut pos = 0;
+
+    while !delta.is_empty() {
+        pos += take_varint(&mut delta);
+        let len = take_varint(&mut delta);
+        for (byte, xor) in state[pos..pos + len].iter_mut().zip(&delta[..len]) {
+            *byte ^= xor;
+        }
+        pos += len;
+        delta = &delta[len..];
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn take_varint(data: &mut &[u8]) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[0];
+        *data = &data[1..];
+        val |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_restores_old_state() {
+        let old: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut new = old.clone();
+        // Changes at both ends and runs and skips too long for one varint byte
+        new[0] ^= 1;
+        new[300..500].fill(0xaa);
+        new[999] ^= 0xff;
+
+        let delta = encode_delta(&new, &old);
+        let mut state = new.clone();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, old);
+
+        assert!(encode_delta(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn pops_states_in_reverse() {
+        let states: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i, 0, i * 2, 0x80 | i]).collect();
+        let mut rewind = Rewind::new(3);
+        for state in &states {
+            rewind.push(state.clone());
+        }
+
+        // Only `capacity` frames are kept before the latest
+        for state in states[1..4].iter().rev() {
+            assert_eq!(rewind.pop(), Some(&state[..]));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn length_change_drops_history() {
+        let mut rewind = Rewind::new(10);
+        rewind.push(vec![1, 2, 3]);
+        rewind.push(vec![1, 2, 4]);
+        rewind.push(vec![1, 2, 4, 5]);
+        assert_eq!(rewind.pop(), None);
+
+        rewind.push(vec![1, 2, 4, 6]);
+        assert_eq!(rewind.pop(), Some(&[1, 2, 4, 5][..]));
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -0,0 +1,53 @@
+use std::hash::{BuildHasher, RandomState};
+
+/// Source of the bytes returned by `CXNN`.
+///
+/// The generator is part of the machine state so runs can be replayed from a
+/// seed, and it is saved along with everything else in save states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rng {
+    /// SplitMix64 generator, holding its current state
+    Seeded(u64),
+    /// Bytes handed out in order, wrapping around at the end
+    Scripted { bytes: Vec<u8>, pos: usize },
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Self {
+        Self::Seeded(seed)
+    }
+
+    /// Generator seeded from the OS, for when reproducibility is not needed
+    pub fn from_entropy() -> Self {
+        Self::Seeded(random_seed())
+    }
+
+    /// Replays `bytes` in order. An empty script always yields 0.
+    pub fn scripted(bytes: Vec<u8>) -> Self {
+        Self::Scripted { bytes, pos: 0 }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match self {
+            Self::Seeded(state) => {
+                *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                let mut z = *state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                ((z ^ (z >> 31)) >> 56) as u8
+            }
+            Self::Scripted { bytes, pos } => {
+                let Some(&byte) = bytes.get(*pos) else {
+                    return 0;
+                };
+                *pos = (*pos + 1) % bytes.len();
+                byte
+            }
+        }
+    }
+}
+
+/// Fresh seed from the OS randomness std uses to key its hash maps
+pub fn random_seed() -> u64 {
+    RandomState::new().hash_one(0u64)
+}
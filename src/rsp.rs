@@ -0,0 +1,499 @@
+//! GDB remote serial protocol, so ROMs can be debugged with gdb or an
+//! editor's debugger UI.
+//!
+//! [`GdbSession`] speaks the protocol over any byte stream. Accepting
+//! connections is up to the embedder.
+
+use std::io::{self, Read, Write};
+
+use crate::{Break, Breakpoint, Chip8};
+
+/// Register layout reported to gdb: V0 to VF, then I, PC and SP
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8em.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// What the embedder should do after the session handled its packets
+#[derive(Debug, PartialEq)]
+pub enum GdbControl {
+    Continue,
+    Pause,
+    /// Execute one instruction, ending frames first while a draw waits for
+    /// vblank, then report how it went like after [`GdbControl::Continue`]
+    Step,
+    /// gdb detached or killed the program, so the session is over and
+    /// execution goes on without it
+    Detach,
+}
+
+/// A gdb connection over `stream`, which should be non-blocking so
+/// [`GdbSession::poll`] returns once everything received is handled
+pub struct GdbSession<S> {
+    stream: S,
+    framer: Framer,
+    /// Whether gdb is waiting for a stop reply
+    running: bool,
+}
+
+impl<S: Read + Write> GdbSession<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, framer: Framer::default(), running: false }
+    }
+
+    /// Handles the packets received since the last call. Fails with
+    /// [`io::ErrorKind::UnexpectedEof`] once gdb closed the connection.
+    pub fn poll(&mut self, chip8: &mut Chip8) -> io::Result<Option<GdbControl>> {
+        let mut control = None;
+        let mut buf = [0; 1024];
+        loop {
+            let len = match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(control),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            for &byte in &buf[..len] {
+                match self.framer.push(byte) {
+                    Some(Frame::Interrupt) => {
+                        if self.running {
+                            self.stopped(SIGINT, None)?;
+                        }
+                        control = Some(GdbControl::Pause);
+                    }
+                    Some(Frame::Corrupt) => self.stream.write_all(b"-")?,
+                    Some(Frame::Packet(packet)) => {
+                        self.stream.write_all(b"+")?;
+                        match self.handle(chip8, &packet)? {
+                            Some(GdbControl::Detach) => return Ok(Some(GdbControl::Detach)),
+                            Some(c) => control = Some(c),
+                            None => {}
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Tells gdb that a single step finished without hitting a breakpoint
+    pub fn report_step(&mut self) -> io::Result<()> {
+        if self.running {
+            self.stopped(SIGTRAP, None)?;
+        }
+        Ok(())
+    }
+
+    /// Tells gdb that the embedder paused execution on its own
+    pub fn report_pause(&mut self) -> io::Result<()> {
+        if self.running {
+            self.stopped(SIGINT, None)?;
+        }
+        Ok(())
+    }
+
+    /// Tells gdb that execution stopped at a breakpoint
+    pub fn report_break(&mut self, hit: Break) -> io::Result<()> {
+        if self.running {
+            self.stopped(SIGTRAP, Some(hit))?;
+        }
+        Ok(())
+    }
+
+    /// Tells gdb that execution stopped on a fault
+    pub fn report_fault(&mut self) -> io::Result<()> {
+        if self.running {
+            self.stopped(SIGSEGV, None)?;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, signal: u8, hit: Option<Break>) -> io::Result<()> {
+        self.running = false;
+        let reply = match hit {
+            Some(Break::Write { addr, .. }) => format!("T{signal:02x}watch:{addr:x};"),
+            Some(Break::Read { addr, .. }) => format!("T{signal:02x}rwatch:{addr:x};"),
+            _ => format!("S{signal:02x}"),
+        };
+        self.send(&reply)
+    }
+
+    fn handle(&mut self, chip8: &mut Chip8, packet: &str) -> io::Result<Option<GdbControl>> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => {
+                let mut regs = hex(chip8.v());
+                regs += &hex(&(chip8.i() as u16).to_le_bytes());
+                regs += &hex(&(chip8.pc() as u16).to_le_bytes());
+                regs += &hex(&[chip8.sp() as u8]);
+                regs
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg @ 0..16) => hex(&[chip8.v()[reg]]),
+                Ok(16) => hex(&(chip8.i() as u16).to_le_bytes()),
+                Ok(17) => hex(&(chip8.pc() as u16).to_le_bytes()),
+                Ok(18) => hex(&[chip8.sp() as u8]),
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_pair(args, ',') {
+                Some((addr, len)) => {
+                    let memory = chip8.memory();
+                    let start = addr.min(memory.len());
+                    let end = addr.saturating_add(len).min(memory.len());
+                    if start == end && len > 0 {
+                        "E01".to_string()
+                    } else {
+                        hex(&memory[start..end])
+                    }
+                }
+                None => "E01".to_string(),
+            },
+            "M" => match write_memory(chip8, args) {
+                Some(()) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            "Z" | "z" => match parse_breakpoint(args) {
+                Ok(breakpoints) => {
+                    for breakpoint in breakpoints {
+                        if command == "Z" {
+                            chip8.add_breakpoint(breakpoint);
+                        } else {
+                            chip8.remove_breakpoint(&breakpoint);
+                        }
+                    }
+                    "OK".to_string()
+                }
+                Err(reply) => reply.to_string(),
+            },
+            // Stepping may have to end a frame first, which is up to the
+            // embedder, so it reports back once the instruction ran
+            "s" => {
+                self.running = true;
+                return Ok(Some(GdbControl::Step));
+            }
+            "c" => {
+                self.running = true;
+                return Ok(Some(GdbControl::Continue));
+            }
+            "D" => {
+                self.send("OK")?;
+                return Ok(Some(GdbControl::Detach));
+            }
+            "k" => return Ok(Some(GdbControl::Detach)),
+            "H" => "OK".to_string(),
+            "q" => query(args),
+            _ => String::new(),
+        };
+
+        self.send(&reply)?;
+        Ok(None)
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${data}#{:02x}", checksum(data.as_bytes()))
+    }
+}
+
+/// Answers `q` packets, given without the leading `q`
+fn query(query: &str) -> String {
+    if query.starts_with("Supported") {
+        return "PacketSize=4000;qXfer:features:read+".to_string();
+    }
+    if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+        let Some((offset, len)) = parse_pair(range, ',') else {
+            return "E01".to_string();
+        };
+        let start = offset.min(TARGET_XML.len());
+        let end = offset.saturating_add(len).min(TARGET_XML.len());
+        let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+        return format!("{more}{}", &TARGET_XML[start..end]);
+    }
+
+    match query {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Applies `addr,len:bytes` of an `M` packet, if it fits in memory
+fn write_memory(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, len) = parse_pair(range, ',')?;
+    if data.len() != len.checked_mul(2)? || !data.is_ascii() {
+        return None;
+    }
+    let bytes = (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&data[i..i + 2], 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    chip8.memory_mut().get_mut(addr..addr.checked_add(len)?)?.copy_from_slice(&bytes);
+    Some(())
+}
+
+/// Maps `type,addr,kind` of a `Z`/`z` packet onto breakpoints, failing
+/// with the reply to send otherwise
+fn parse_breakpoint(args: &str) -> Result<Vec<Breakpoint>, &'static str> {
+    let (kind, rest) = args.split_once(',').ok_or("E01")?;
+    let (addr, len) = parse_pair(rest, ',').ok_or("E01")?;
+    let end = addr.checked_add(len.max(1) - 1).ok_or("E01")?;
+    let range = addr..=end;
+
+    match kind {
+        // Software and hardware breakpoints are the same thing here
+        "0" | "1" => Ok(vec![Breakpoint::Pc(addr)]),
+        "2" => Ok(vec![Breakpoint::Write(range)]),
+        "3" => Ok(vec![Breakpoint::Read(range)]),
+        "4" => Ok(vec![Breakpoint::Read(range.clone()), Breakpoint::Write(range)]),
+        // An empty reply tells gdb the kind is not supported
+        _ => Err(""),
+    }
+}
+
+fn parse_pair(s: &str, separator: char) -> Option<(usize, usize)> {
+    let (a, b) = s.split_once(separator)?;
+    Some((usize::from_str_radix(a, 16).ok()?, usize::from_str_radix(b, 16).ok()?))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+enum Frame {
+    Packet(String),
+    /// A packet whose checksum did not match, to be sent again
+    Corrupt,
+    /// Ctrl-C from the debugger
+    Interrupt,
+}
+
+/// Splits the incoming byte stream into packets
+#[derive(Default)]
+struct Framer {
+    packet: Option<Vec<u8>>,
+    /// Checksum digits received after the `#` ending the packet
+    checksum: Option<Vec<u8>>,
+}
+
+impl Framer {
+    fn push(&mut self, byte: u8) -> Option<Frame> {
+        match (&mut self.packet, &mut self.checksum) {
+            (None, _) if byte == 0x03 => return Some(Frame::Interrupt),
+            (None, _) if byte == b'$' => self.packet = Some(vec![]),
+            // Acks from gdb and noise between packets
+            (None, _) => {}
+            (Some(_), None) if byte == b'#' => self.checksum = Some(vec![]),
+            (Some(data), None) => data.push(byte),
+            (Some(_), Some(digits)) => {
+                digits.push(byte);
+                if digits.len() == 2 {
+                    let data = self.packet.take().unwrap_or_default();
+                    let digits = self.checksum.take().unwrap_or_default();
+                    let expected = std::str::from_utf8(&digits)
+                        .ok()
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                    if expected != Some(checksum(&data)) {
+                        return Some(Frame::Corrupt);
+                    }
+                    return Some(Frame::Packet(String::from_utf8_lossy(&data).into_owned()));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    /// Stream fed with scripted input, as if from a non-blocking socket
+    #[derive(Default)]
+    struct Script {
+        input: Vec<u8>,
+        read: usize,
+        output: Vec<u8>,
+        /// Whether gdb hung up once the input is read
+        closed: bool,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let rest = &self.input[self.read..];
+            if rest.is_empty() && self.closed {
+                return Ok(0);
+            }
+            if rest.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let len = rest.len().min(buf.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            self.read += len;
+            Ok(len)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${data}#{:02x}", checksum(data.as_bytes()))
+    }
+
+    /// Sends `request` and returns the control and everything written back
+    fn exchange(
+        session: &mut GdbSession<Script>,
+        chip8: &mut Chip8,
+        request: &str,
+    ) -> (Option<GdbControl>, String) {
+        session.stream.input.extend_from_slice(request.as_bytes());
+        let control = session.poll(chip8).unwrap();
+        (control, take_output(session))
+    }
+
+    fn take_output(session: &mut GdbSession<Script>) -> String {
+        String::from_utf8(std::mem::take(&mut session.stream.output)).unwrap()
+    }
+
+    fn setup() -> (GdbSession<Script>, Chip8) {
+        // V0 := 0x12, then I := 0x300 and loop
+        let rom = vec![0x60, 0x12, 0xa3, 0x00, 0x12, 0x04];
+        (GdbSession::new(Script::default()), Chip8::new(rom, Quirks::default()).unwrap())
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let (mut session, mut chip8) = setup();
+        let ack = |reply: &str| format!("+{}", packet(reply));
+
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("?")), (None, ack("S05")));
+        let regs = format!("{}00000002{}", "00".repeat(16), "00");
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("g")), (None, ack(&regs)));
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("m200,4")), (None, ack("6012a300")));
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("M300,2:beef")), (None, ack("OK")));
+        assert_eq!(&chip8.memory()[0x300..0x302], [0xbe, 0xef]);
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("m300,2")), (None, ack("beef")));
+
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("M300,2:be")), (None, ack("E01")));
+        let past_end = exchange(&mut session, &mut chip8, &packet("Mffff,2:beef"));
+        assert_eq!(past_end, (None, ack("E01")));
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("m10000,4")), (None, ack("E01")));
+    }
+
+    #[test]
+    fn stepping_and_breakpoints() {
+        let (mut session, mut chip8) = setup();
+        let ack = |reply: &str| format!("+{}", packet(reply));
+
+        // The embedder executes the step and reports back
+        let step = exchange(&mut session, &mut chip8, &packet("s"));
+        assert_eq!(step, (Some(GdbControl::Step), "+".to_string()));
+        assert_eq!(chip8.step(), Ok(None));
+        session.report_step().unwrap();
+        assert_eq!(take_output(&mut session), packet("S05"));
+        assert_eq!(chip8.v()[0], 0x12);
+
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("Z0,204,2")), (None, ack("OK")));
+        assert_eq!(chip8.breakpoints(), [Breakpoint::Pc(0x204)]);
+        assert_eq!(
+            exchange(&mut session, &mut chip8, &packet("c")),
+            (Some(GdbControl::Continue), "+".to_string())
+        );
+        // The embedder runs the machine until the breakpoint hits
+        let hit = chip8.step().unwrap().unwrap();
+        session.report_break(hit).unwrap();
+        assert_eq!(take_output(&mut session), packet("S05"));
+
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("z0,204,2")), (None, ack("OK")));
+        assert!(chip8.breakpoints().is_empty());
+        // An unsupported kind gets an empty reply, a range past the end of
+        // the address space an error
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("Z5,204,2")), (None, ack("")));
+        let overflow = format!("Z2,{:x},2", usize::MAX);
+        assert_eq!(exchange(&mut session, &mut chip8, &packet(&overflow)), (None, ack("E01")));
+    }
+
+    #[test]
+    fn stop_replies() {
+        let (mut session, mut chip8) = setup();
+
+        // Nothing is sent while gdb is not waiting for execution to stop
+        session.report_pause().unwrap();
+        session.report_step().unwrap();
+        assert_eq!(take_output(&mut session), "");
+
+        exchange(&mut session, &mut chip8, &packet("c"));
+        session.report_pause().unwrap();
+        assert_eq!(take_output(&mut session), packet("S02"));
+
+        exchange(&mut session, &mut chip8, &packet("c"));
+        session.report_fault().unwrap();
+        session.report_pause().unwrap();
+        assert_eq!(take_output(&mut session), packet("S0b"));
+    }
+
+    #[test]
+    fn framing() {
+        let (mut session, mut chip8) = setup();
+
+        // A bad checksum asks gdb to send the packet again
+        assert_eq!(exchange(&mut session, &mut chip8, "$?#00"), (None, "-".to_string()));
+        // Acks and packets split across reads
+        let (_, output) = exchange(&mut session, &mut chip8, "+$?");
+        assert_eq!(output, "");
+        let (_, output) = exchange(&mut session, &mut chip8, "#3f");
+        assert_eq!(output, format!("+{}", packet("S05")));
+
+        let interrupt = exchange(&mut session, &mut chip8, "\x03");
+        assert_eq!(interrupt, (Some(GdbControl::Pause), String::new()));
+        assert_eq!(exchange(&mut session, &mut chip8, &packet("D")).0, Some(GdbControl::Detach));
+
+        session.stream.closed = true;
+        let error = session.poll(&mut chip8).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
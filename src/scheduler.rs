@@ -0,0 +1,98 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Length of one frame at the 60Hz the timers and display run at
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Frames run at most per call, so a stall (e.g. the window being dragged)
+/// does not make the emulator race to catch up afterwards
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+/// Wall-clock time spent emulating per redraw before yielding to rendering,
+/// which is what bounds unlimited fast-forward
+pub const WORK_BUDGET: Duration = Duration::from_millis(12);
+
+/// How fast emulated time runs compared to wall-clock time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Normal,
+    Double,
+    Quadruple,
+    Unlimited,
+}
+
+impl Speed {
+    pub const NAMES: [(&'static str, Self); 4] = [
+        ("1", Self::Normal),
+        ("2", Self::Double),
+        ("4", Self::Quadruple),
+        ("max", Self::Unlimited),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(speed, _)| speed.eq_ignore_ascii_case(name)).map(|(_, speed)| *speed)
+    }
+
+    /// Frames to emulate for `frames` frames of wall-clock time.
+    ///
+    /// At unlimited speed this is unbounded and the caller has to stop once
+    /// it exceeds [`WORK_BUDGET`].
+    pub fn scale(self, frames: u32) -> u32 {
+        match self {
+            Self::Normal => frames,
+            Self::Double => frames * 2,
+            Self::Quadruple => frames * 4,
+            Self::Unlimited if frames > 0 => u32::MAX,
+            Self::Unlimited => 0,
+        }
+    }
+
+    /// The next fast-forward setting, wrapping back to normal speed
+    pub fn next(self) -> Self {
+        match self {
+            Self::Normal => Self::Double,
+            Self::Double => Self::Quadruple,
+            Self::Quadruple => Self::Unlimited,
+            Self::Unlimited => Self::Normal,
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f, "1x"),
+            Self::Double => write!(f, "2x"),
+            Self::Quadruple => write!(f, "4x"),
+            Self::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// Fixed timestep scheduler dividing wall-clock time into 60Hz frames.
+///
+/// Time left over that does not make up a whole frame is carried into the
+/// next call rather than dropped.
+pub struct Scheduler {
+    last: Instant,
+    acc: Duration,
+    pub cycles_per_frame: u32,
+    pub speed: Speed,
+}
+
+impl Scheduler {
+    pub fn new(cycles_per_frame: u32, speed: Speed) -> Self {
+        Self { last: Instant::now(), acc: Duration::ZERO, cycles_per_frame, speed }
+    }
+
+    /// Number of whole frames of wall-clock time passed since the last call
+    pub fn frames_due(&mut self) -> u32 {
+        let now = Instant::now();
+        self.acc += now - self.last;
+        self.last = now;
+
+        let frames = (self.acc.as_nanos() / FRAME.as_nanos()) as u32;
+        self.acc -= FRAME * frames;
+        frames.min(MAX_CATCH_UP_FRAMES)
+    }
+}
@@ -0,0 +1,44 @@
+// Draws the framebuffer texture as a single quad covering the viewport
+
+struct Screen {
+	// Colors for pixel values 0 to 3: off, plane 1, plane 2 and both planes
+	colors: array<vec4<f32>, 4>,
+	// Left, top, right and bottom edges in clip space
+	viewport: vec4<f32>,
+	// Pixels of the texture in use for the current resolution
+	size: vec2<u32>,
+};
+
+@group(0) @binding(0) var<uniform> screen: Screen;
+// Brightness of plane 1 and plane 2 of each pixel
+@group(0) @binding(1) var pixels: texture_2d<f32>;
+
+struct VertexOutput {
+	@builtin(position) position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	// Triangle strip corners: top-left, bottom-left, top-right, bottom-right
+	let uv = vec2<f32>(f32(index / 2u), f32(index % 2u));
+	var out: VertexOutput;
+	out.uv = uv;
+	out.position = vec4<f32>(
+		mix(screen.viewport.x, screen.viewport.z, uv.x),
+		mix(screen.viewport.y, screen.viewport.w, uv.y),
+		0.0,
+		1.0,
+	);
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let texel = min(vec2<u32>(in.uv * vec2<f32>(screen.size)), screen.size - 1u);
+	let planes = textureLoad(pixels, texel, 0).rg;
+	// Fading pixels are partly lit, so mix between the colors of the planes
+	let plane2_off = mix(screen.colors[0], screen.colors[1], planes.x);
+	let plane2_on = mix(screen.colors[2], screen.colors[3], planes.x);
+	return mix(plane2_off, plane2_on, planes.y);
+}
@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive};
+
+use crate::error::AsmError;
+
+/// Names of addresses in a program, as generated by [`assemble`] or read
+/// from a symbol file.
+///
+/// [`assemble`]: crate::assemble
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    addrs: BTreeMap<String, usize>,
+    /// First name given to each address
+    names: BTreeMap<usize, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a symbol file with one `name 0xADDR` or `name = 0xADDR` pair
+    /// per line. Blank lines and `#` comments are ignored.
+    pub fn parse(text: &str) -> Result<Self, AsmError> {
+        let mut symbols = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| AsmError { line: i + 1, message };
+            let code = line.split('#').next().unwrap_or_default();
+            let fields: Vec<_> = code.split_whitespace().filter(|&field| field != "=").collect();
+
+            match fields[..] {
+                [] => {}
+                [name, addr] => {
+                    let value = match addr.strip_prefix("0x") {
+                        Some(hex) => usize::from_str_radix(hex, 16),
+                        None => addr.parse(),
+                    };
+                    let addr = value.map_err(|_| error(format!("invalid address {addr}")))?;
+                    symbols.insert(name.to_string(), addr);
+                }
+                _ => return Err(error(format!("expected a name and an address, found {code}"))),
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Names `addr`, moving the label if it already named another address
+    pub fn insert(&mut self, name: String, addr: usize) {
+        if let Some(old) = self.addrs.insert(name.clone(), addr)
+            && old != addr
+            && self.names.get(&old) == Some(&name)
+        {
+            // Fall back to another label of the old address, if it has one
+            match self.addrs.iter().find(|&(_, &a)| a == old) {
+                Some((other, _)) => self.names.insert(old, other.clone()),
+                None => self.names.remove(&old),
+            };
+        }
+        self.names.entry(addr).or_insert(name);
+    }
+
+    /// Address of the label `name`
+    pub fn addr(&self, name: &str) -> Option<usize> {
+        self.addrs.get(name).copied()
+    }
+
+    /// Address of a label, or of a hex number with a `0x` prefix. Without
+    /// the prefix, labels such as `add` or `beef` would pass for numbers.
+    pub fn resolve(&self, s: &str) -> Option<usize> {
+        self.addr(s).or_else(|| usize::from_str_radix(s.strip_prefix("0x")?, 16).ok())
+    }
+
+    /// Resolves `<addr>` or `<addr>-<addr>` into an inclusive range
+    pub fn resolve_range(&self, s: &str) -> Option<RangeInclusive<usize>> {
+        // Octo labels may contain dashes, so a whole label is not a range
+        match self.resolve(s) {
+            Some(addr) => Some(addr..=addr),
+            None => {
+                let (start, end) = s.split_once('-')?;
+                Some(self.resolve(start)?..=self.resolve(end)?)
+            }
+        }
+    }
+
+    /// Label at exactly `addr`
+    pub fn name(&self, addr: usize) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    /// Labels ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.addrs.iter().map(|(name, &addr)| (name.as_str(), addr))
+    }
+
+    /// Shows `addr` in hex, followed by the closest label at or before it,
+    /// such as `0x2a6 <draw-player+0x2>`
+    pub fn describe(&self, addr: usize) -> String {
+        match self.names.range(..=addr).next_back() {
+            Some((&start, name)) if start == addr => format!("{addr:#05x} <{name}>"),
+            Some((&start, name)) => format!("{addr:#05x} <{name}+{:#x}>", addr - start),
+            None => format!("{addr:#05x}"),
+        }
+    }
+}
+
+/// Writes the symbols in the format read by [`Symbols::parse`]
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, addr) in self.iter() {
+            writeln!(f, "{name} {addr:#06x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_a_label() {
+        let mut symbols = Symbols::new();
+        symbols.insert("draw".to_string(), 0x200);
+        symbols.insert("draw".to_string(), 0x210);
+        assert_eq!(symbols.name(0x200), None);
+        assert_eq!(symbols.name(0x210), Some("draw"));
+
+        symbols.insert("start".to_string(), 0x210);
+        symbols.insert("alias".to_string(), 0x210);
+        symbols.insert("draw".to_string(), 0x220);
+        assert_eq!(symbols.name(0x210), Some("alias"));
+        assert_eq!(symbols.describe(0x212), "0x212 <alias+0x2>");
+    }
+
+    #[test]
+    fn resolving() {
+        let symbols = Symbols::parse("add 0x2a0\nsprite-data = 0x300").unwrap();
+        assert_eq!(symbols.resolve("add"), Some(0x2a0));
+        assert_eq!(symbols.resolve("0x2a4"), Some(0x2a4));
+        assert_eq!(symbols.resolve("beef"), None);
+        assert_eq!(symbols.resolve("2a4"), None);
+        assert_eq!(symbols.resolve_range("sprite-data"), Some(0x300..=0x300));
+        assert_eq!(symbols.resolve_range("add-0x2af"), Some(0x2a0..=0x2af));
+        assert_eq!(symbols.resolve_range("0x300-beef"), None);
+    }
+}
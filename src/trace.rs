@@ -0,0 +1,291 @@
+use std::{collections::VecDeque, fmt, io::Write, ops::RangeInclusive};
+
+use crate::{
+    breakpoint::Register,
+    disasm::{Instruction, InstructionClass, Syntax},
+    symbols::Symbols,
+};
+
+/// Target of the trace log messages, so they can be enabled with
+/// `RUST_LOG=chip8em::trace=trace`
+const LOG_TARGET: &str = "chip8em::trace";
+
+/// Which executed instructions a [`Tracer`] records
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Addresses of the instructions to record, or all of them
+    pub range: Option<RangeInclusive<usize>>,
+    /// Kinds of instructions to record, or all of them if empty
+    pub classes: Vec<InstructionClass>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: usize, instruction: &Instruction) -> bool {
+        self.range.as_ref().is_none_or(|range| range.contains(&pc))
+            && (self.classes.is_empty() || self.classes.contains(&instruction.class()))
+    }
+}
+
+/// An executed instruction and its effects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub op: u16,
+    /// The address following `F000`
+    pub long: u16,
+    /// Registers the instruction changed, with their old and new values
+    pub changes: Vec<(Register, usize, usize)>,
+    /// Memory the instruction wrote, in order
+    pub writes: Vec<(usize, u8)>,
+}
+
+impl TraceEntry {
+    /// Like the [`Display`](fmt::Display) output, with addresses labeled
+    /// from `symbols`
+    pub fn describe(&self, symbols: &Symbols) -> String {
+        let label = |addr: u16| symbols.name(addr as usize).map(str::to_string);
+        let text = match Instruction::decode(self.op) {
+            Some(instruction) => instruction.format(Syntax::Cowboy, self.long, &label),
+            None => "???".to_string(),
+        };
+
+        let mut line = format!("{:<24} {:04X}  {text:<20}", symbols.describe(self.pc), self.op);
+        for (register, old, new) in &self.changes {
+            line += &format!(" {register}:{old:#x}->{new:#x}");
+        }
+        for (addr, value) in &self.writes {
+            line += &format!(" [{addr:#05x}]={value:#04x}");
+        }
+        line.trim_end().to_string()
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(&Symbols::new()))
+    }
+}
+
+/// Where a [`Tracer`] writes its entries
+pub enum TraceOutput {
+    /// `log::trace!` messages, or `log::error!` when dumping the ring buffer
+    Log,
+    Writer(Box<dyn Write + Send>),
+}
+
+/// Records executed instructions, see [`Chip8::set_tracer`].
+///
+/// [`Chip8::set_tracer`]: crate::Chip8::set_tracer
+pub struct Tracer {
+    filter: TraceFilter,
+    output: TraceOutput,
+    symbols: Symbols,
+    /// Most recent entries, only written out by [`Tracer::dump`], when
+    /// tracing to a ring buffer
+    ring: Option<(usize, VecDeque<TraceEntry>)>,
+    /// Writes by the instruction being executed
+    pub(crate) writes: Vec<(usize, u8)>,
+}
+
+impl Tracer {
+    /// Creates a tracer writing every matching instruction to `output`, or
+    /// keeping only the last `ring` of them until they are dumped
+    pub fn new(filter: TraceFilter, output: TraceOutput, ring: Option<usize>) -> Self {
+        Self {
+            filter,
+            output,
+            symbols: Symbols::new(),
+            ring: ring.map(|capacity| (capacity, VecDeque::with_capacity(capacity))),
+            writes: vec![],
+        }
+    }
+
+    /// Labels addresses in the output
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        match &mut self.ring {
+            Some((capacity, entries)) => {
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                if *capacity > 0 {
+                    entries.push_back(entry);
+                }
+            }
+            None => {
+                // Formatting every instruction is slow, so skip it when the
+                // log would drop the message anyway
+                let logged = log::log_enabled!(target: LOG_TARGET, log::Level::Trace);
+                if matches!(self.output, TraceOutput::Writer(_)) || logged {
+                    let line = entry.describe(&self.symbols);
+                    self.write(log::Level::Trace, &line);
+                }
+            }
+        }
+    }
+
+    /// Writes out and clears the ring buffer, after `reason` explaining why
+    pub fn dump(&mut self, reason: &str) {
+        let Some((_, entries)) = &mut self.ring else {
+            return;
+        };
+        let entries = std::mem::take(entries);
+
+        let header = format!("Last {} instructions before {reason}:", entries.len());
+        self.write(log::Level::Error, &header);
+        for entry in entries {
+            let line = entry.describe(&self.symbols);
+            self.write(log::Level::Error, &line);
+        }
+        if let TraceOutput::Writer(writer) = &mut self.output
+            && let Err(e) = writer.flush()
+        {
+            log::warn!("Unable to write trace: {e}");
+        }
+    }
+
+    fn write(&mut self, level: log::Level, line: &str) {
+        match &mut self.output {
+            TraceOutput::Log => log::log!(target: LOG_TARGET, level, "{line}"),
+            TraceOutput::Writer(writer) => {
+                if let Err(e) = writeln!(writer, "{line}") {
+                    log::warn!("Unable to write trace: {e}");
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("filter", &self.filter)
+            .field("ring", &self.ring.as_ref().map(|(capacity, _)| capacity))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{Chip8, Quirks};
+
+    /// Writer whose output stays readable after the tracer took it
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn entry(pc: usize, op: u16) -> TraceEntry {
+        TraceEntry { pc, op, long: 0, changes: vec![], writes: vec![] }
+    }
+
+    #[test]
+    fn filter() {
+        let draw = Instruction::Draw { x: 0, y: 1, n: 5 };
+        let jump = Instruction::Jump(0x200);
+        assert!(TraceFilter::default().matches(0x200, &draw));
+
+        let range = TraceFilter { range: Some(0x204..=0x208), classes: vec![] };
+        assert!(!range.matches(0x202, &draw));
+        assert!(range.matches(0x204, &draw));
+        assert!(range.matches(0x208, &draw));
+        assert!(!range.matches(0x20a, &draw));
+
+        let classes = TraceFilter { range: None, classes: vec![InstructionClass::Display] };
+        assert!(classes.matches(0x300, &draw));
+        assert!(!classes.matches(0x300, &jump));
+
+        let both = TraceFilter { range: Some(0x204..=0x208), ..classes };
+        assert!(both.matches(0x206, &draw));
+        assert!(!both.matches(0x20a, &draw));
+        assert!(!both.matches(0x206, &jump));
+    }
+
+    #[test]
+    fn ring_keeps_the_last_entries() {
+        let output = Output::default();
+        let writer = TraceOutput::Writer(Box::new(output.clone()));
+        let mut tracer = Tracer::new(TraceFilter::default(), writer, Some(2));
+        for (i, op) in [0x00e0, 0x6105, 0x7101].into_iter().enumerate() {
+            tracer.record(entry(0x200 + i * 2, op));
+        }
+        assert_eq!(output.text(), "", "the ring is only written out by dump");
+
+        tracer.dump("a test");
+        assert_eq!(
+            output.text(),
+            "Last 2 instructions before a test:
+0x202                    6105  LD V1, 0x05
+0x204                    7101  ADD V1, 0x01
+"
+        );
+
+        // Dumping empties the ring
+        tracer.dump("another test");
+        assert!(output.text().ends_with("Last 0 instructions before another test:\n"));
+    }
+
+    #[test]
+    fn writes_every_entry_without_a_ring() {
+        let output = Output::default();
+        let writer = TraceOutput::Writer(Box::new(output.clone()));
+        let mut tracer = Tracer::new(TraceFilter::default(), writer, None);
+        let mut symbols = Symbols::new();
+        symbols.insert("main".to_string(), 0x200);
+        tracer.set_symbols(symbols);
+
+        tracer.record(TraceEntry {
+            changes: vec![(Register::I, 0x20e, 0x210)],
+            writes: vec![(0x20e, 5)],
+            ..entry(0x204, 0xf055)
+        });
+        assert_eq!(
+            output.text(),
+            "0x204 <main+0x4>         F055  LD [I], V0           I:0x20e->0x210 [0x20e]=0x05\n"
+        );
+    }
+
+    #[test]
+    fn dump_ends_at_an_invalid_opcode() {
+        // Only flow instructions are traced, which the invalid 0x5121 is not
+        let filter = TraceFilter { range: None, classes: vec![InstructionClass::Flow] };
+        let output = Output::default();
+        let tracer = Tracer::new(filter, TraceOutput::Writer(Box::new(output.clone())), Some(8));
+        let program = vec![0x60, 0x01, 0x12, 0x04, 0x51, 0x21];
+        let mut chip8 = Chip8::new(program, Quirks::default()).unwrap();
+        chip8.set_tracer(Some(tracer));
+
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        assert!(chip8.step().is_err());
+        let lines: Vec<_> = output.text().lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 3, "{lines:?}");
+        assert!(lines[1].contains("JP 0x204"), "{}", lines[1]);
+        assert!(lines[2].starts_with("0x204                    5121  ???"), "{}", lines[2]);
+    }
+}
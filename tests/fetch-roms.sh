@@ -0,0 +1,19 @@
+#!/bin/sh
+# Copies the Timendus CHIP-8 test suite ROMs into tests/roms for
+# tests/timendus.rs. Takes the commit or tag to fetch, the default branch
+# otherwise, and prints the commit it got so that it can be pinned.
+set -eu
+
+repo=https://github.com/Timendus/chip8-test-suite
+ref=${1:-HEAD}
+roms=$(dirname "$0")/roms
+checkout=$(mktemp -d)
+trap 'rm -rf "$checkout"' EXIT
+
+git -C "$checkout" init -q
+git -C "$checkout" fetch -q --depth 1 "$repo" "$ref"
+git -C "$checkout" checkout -q FETCH_HEAD
+
+mkdir -p "$roms"
+cp "$checkout"/bin/*.ch8 "$roms"
+echo "Fetched $(git -C "$checkout" rev-parse HEAD) into $roms"
@@ -0,0 +1,233 @@
+//! Golden framebuffer tests driven by the Timendus CHIP-8 test suite.
+//!
+//! The ROMs are not distributed with this crate. `tests/fetch-roms.sh`
+//! copies the `.ch8` files of https://github.com/Timendus/chip8-test-suite
+//! into `tests/roms/`, and the cases using them are skipped while a ROM is
+//! missing. Set `CHIP8_REQUIRE_ROMS=1`, as CI should after fetching them, to
+//! fail instead.
+//!
+//! Expected framebuffers live in `tests/golden/<case>.txt`, in the text
+//! format of [`Chip8::screen_text`], which `chip8em-headless --screen` also
+//! writes. After an intended change in behavior, regenerate them with
+//! `CHIP8_BLESS=1 cargo test --test timendus` and review the diff before
+//! committing.
+
+use std::{env, fs, path::PathBuf};
+
+use chip8em::{Chip8, Quirks, Rng};
+
+struct Case {
+    /// Name of the golden file
+    name: &'static str,
+    rom: &'static str,
+    quirks: Quirks,
+    frames: u64,
+    cycles_per_frame: u64,
+    /// (frame, key, frames held)
+    keys: &'static [(u64, u8, u64)],
+}
+
+/// Runs `frames` frames of `cycles_per_frame` instructions, pressing `keys`
+/// as (frame, key, frames held)
+fn run(chip8: &mut Chip8, name: &str, frames: u64, cycles_per_frame: u64, keys: &[(u64, u8, u64)]) {
+    for frame in 0..frames {
+        for &(start, key, held) in keys {
+            if start == frame {
+                chip8.press_key(key);
+            } else if start + held == frame {
+                chip8.release_key(key);
+            }
+        }
+
+        for _ in 0..cycles_per_frame {
+            if chip8.exited() {
+                return;
+            }
+            if let Err(e) = chip8.step() {
+                panic!("{name}: {e}");
+            }
+        }
+
+        chip8.tick_timers();
+    }
+}
+
+/// Compares `actual` against the golden file of `name`, or rewrites it when
+/// blessing
+fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/golden/{name}.txt"));
+
+    if env::var_os("CHIP8_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let Ok(expected) = fs::read_to_string(&path) else {
+        panic!("{name}: missing {}, run with CHIP8_BLESS=1 to create it", path.display());
+    };
+    if expected == actual {
+        return;
+    }
+
+    let mut diff = String::new();
+    let expected_rows: Vec<_> = expected.lines().collect();
+    let actual_rows: Vec<_> = actual.lines().collect();
+    for row in 0..expected_rows.len().max(actual_rows.len()) {
+        let expected = expected_rows.get(row).copied().unwrap_or("");
+        let actual = actual_rows.get(row).copied().unwrap_or("");
+        if expected != actual {
+            diff += &format!("row {row:>2} expected {expected}\n       actual   {actual}\n");
+        }
+    }
+    panic!("{name}: framebuffer differs from {}\n{diff}", path.display());
+}
+
+fn check(case: Case) {
+    let rom_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(case.rom);
+    let Ok(rom) = fs::read(&rom_path) else {
+        let missing = format!("{}: {} not found", case.name, rom_path.display());
+        if env::var_os("CHIP8_REQUIRE_ROMS").is_some() {
+            panic!("{missing}");
+        }
+        eprintln!("{missing}, skipped, run tests/fetch-roms.sh to get it");
+        return;
+    };
+
+    let mut chip8 = Chip8::new(rom, case.quirks).unwrap();
+    chip8.set_rng(Rng::seeded(0));
+    run(&mut chip8, case.name, case.frames, case.cycles_per_frame, case.keys);
+    assert_golden(case.name, &chip8.screen_text());
+}
+
+macro_rules! golden_tests {
+    ($($test:ident: $case:expr,)*) => {
+        $(
+            #[test]
+            fn $test() {
+                check($case);
+            }
+        )*
+    };
+}
+
+golden_tests! {
+    chip8_logo: Case {
+        name: "chip8-logo",
+        rom: "1-chip8-logo.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 60,
+        cycles_per_frame: 15,
+        keys: &[],
+    },
+    ibm_logo: Case {
+        name: "ibm-logo",
+        rom: "2-ibm-logo.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 60,
+        cycles_per_frame: 15,
+        keys: &[],
+    },
+    corax: Case {
+        name: "corax+",
+        rom: "3-corax+.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 120,
+        cycles_per_frame: 15,
+        keys: &[],
+    },
+    flags_vip: Case {
+        name: "flags-vip",
+        rom: "4-flags.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 120,
+        cycles_per_frame: 15,
+        keys: &[],
+    },
+    flags_schip: Case {
+        name: "flags-schip",
+        rom: "4-flags.ch8",
+        quirks: Quirks::SCHIP,
+        frames: 120,
+        cycles_per_frame: 30,
+        keys: &[],
+    },
+    flags_xochip: Case {
+        name: "flags-xochip",
+        rom: "4-flags.ch8",
+        quirks: Quirks::XO_CHIP,
+        frames: 120,
+        cycles_per_frame: 30,
+        keys: &[],
+    },
+    // The quirks test asks for the platform through its menu: 1 for CHIP-8,
+    // 2 then 1 for modern SUPER-CHIP and 3 for XO-CHIP
+    quirks_vip: Case {
+        name: "quirks-vip",
+        rom: "5-quirks.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 600,
+        cycles_per_frame: 15,
+        keys: &[(30, 0x1, 5)],
+    },
+    quirks_schip: Case {
+        name: "quirks-schip",
+        rom: "5-quirks.ch8",
+        quirks: Quirks::SCHIP,
+        frames: 600,
+        cycles_per_frame: 30,
+        keys: &[(30, 0x2, 5), (60, 0x1, 5)],
+    },
+    quirks_xochip: Case {
+        name: "quirks-xochip",
+        rom: "5-quirks.ch8",
+        quirks: Quirks::XO_CHIP,
+        frames: 600,
+        cycles_per_frame: 30,
+        keys: &[(30, 0x3, 5)],
+    },
+    // Selects the FX0A test, then presses and releases A
+    keypad: Case {
+        name: "keypad",
+        rom: "6-keypad.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 180,
+        cycles_per_frame: 15,
+        keys: &[(30, 0x3, 5), (90, 0xa, 5)],
+    },
+    beep: Case {
+        name: "beep",
+        rom: "7-beep.ch8",
+        quirks: Quirks::COSMAC_VIP,
+        frames: 120,
+        cycles_per_frame: 15,
+        keys: &[(30, 0xb, 30)],
+    },
+}
+
+/// Draws every font digit, independently of the external ROMs, so the
+/// harness itself is always exercised
+#[test]
+fn font_digits() {
+    let rom = vec![
+        0x60, 0x00, // V0 = 0
+        0x61, 0x00, // V1 = 0
+        0x62, 0x00, // V2 = 0
+        0xf2, 0x29, // loop: I = sprite(V2)
+        0xd0, 0x15, // draw at V0, V1
+        0x70, 0x05, // V0 += 5
+        0x72, 0x01, // V2 += 1
+        0x32, 0x08, // skip if V2 == 8
+        0x12, 0x16, // jump to next
+        0x60, 0x00, // V0 = 0
+        0x61, 0x06, // V1 = 6
+        0x32, 0x10, // next: skip if V2 == 16
+        0x12, 0x06, // jump to loop
+        0x00, 0xfd, // exit
+    ];
+    let mut chip8 = Chip8::new(rom, Quirks::COSMAC_VIP).unwrap();
+    run(&mut chip8, "font-digits", 60, 15, &[]);
+
+    assert!(chip8.exited());
+    assert_golden("font-digits", &chip8.screen_text());
+}